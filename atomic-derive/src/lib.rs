@@ -0,0 +1,231 @@
+//! `#[derive(ByteEq)]` for the `atomic` crate.
+//!
+//! Implementing [`ByteEq`] by hand is `unsafe`: a struct whose equality is not byte equality, for
+//! example because it has padding bytes, would make byte-wise compare-and-swap give wrong answers.
+//! This derive only emits the implementation after checking at compile time that:
+//!
+//! * every field implements `ByteEq`, and
+//! * the struct has no padding, i.e. its size is the sum of the sizes of its fields.
+//!
+//! What it can't check is the struct's own `PartialEq` implementation, which must compare all
+//! fields with their own `PartialEq`, like `#[derive(PartialEq, Eq)]` does. A hand-written one
+//! that ignores a field or compares case-insensitively would break the contract of `ByteEq`, so
+//! the derive has to be acknowledged with `#[byte_eq(unsafe_assert_structural_eq)]`.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate atomic;
+//! #[macro_use]
+//! extern crate atomic_derive;
+//!
+//! use atomic::AtomicCell;
+//!
+//! #[derive(Clone, Copy, Debug, PartialEq, Eq, ByteEq)]
+//! #[byte_eq(unsafe_assert_structural_eq)]
+//! struct Version {
+//!     major: u16,
+//!     minor: u16,
+//! }
+//!
+//! # fn main() {
+//! let a = AtomicCell::new(Version { major: 1, minor: 0 });
+//! assert!(a.compare_and_set_bytes(Version { major: 1, minor: 0 }, Version { major: 1, minor: 1 }));
+//! # }
+//! ```
+//!
+//! A struct with padding is rejected:
+//!
+//! ```compile_fail
+//! extern crate atomic;
+//! #[macro_use]
+//! extern crate atomic_derive;
+//!
+//! #[derive(PartialEq, Eq, ByteEq)]
+//! #[byte_eq(unsafe_assert_structural_eq)]
+//! struct Padded {
+//!     a: u8,
+//!     b: u32,
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! So is a struct with a field that is not `ByteEq`:
+//!
+//! ```compile_fail
+//! extern crate atomic;
+//! #[macro_use]
+//! extern crate atomic_derive;
+//!
+//! #[derive(PartialEq, Eq, ByteEq)]
+//! #[byte_eq(unsafe_assert_structural_eq)]
+//! struct Named {
+//!     name: &'static str,
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! Enums, unions and generic structs are not supported:
+//!
+//! ```compile_fail
+//! extern crate atomic;
+//! #[macro_use]
+//! extern crate atomic_derive;
+//!
+//! #[derive(PartialEq, Eq, ByteEq)]
+//! #[byte_eq(unsafe_assert_structural_eq)]
+//! struct Wrapper<T>(T);
+//! # fn main() {}
+//! ```
+//!
+//! Without the acknowledgement, the derive is rejected, since it can't tell a derived `PartialEq`
+//! from a hand-written one like this:
+//!
+//! ```compile_fail
+//! extern crate atomic;
+//! #[macro_use]
+//! extern crate atomic_derive;
+//!
+//! #[derive(Eq, ByteEq)]
+//! struct Id {
+//!     id: u32,
+//!     generation: u32,
+//! }
+//!
+//! impl PartialEq for Id {
+//!     fn eq(&self, other: &Id) -> bool {
+//!         self.id == other.id
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! [`ByteEq`]: ../atomic/byte_eq/trait.ByteEq.html
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use syn::spanned::Spanned;
+use syn::{Data, DeriveInput, Error, Fields, Meta, NestedMeta};
+
+#[proc_macro_derive(ByteEq, attributes(byte_eq))]
+pub fn derive_byte_eq(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let name = &input.ident;
+
+    check_acknowledged(input)?;
+
+    let fields = match input.data {
+        Data::Struct(ref data) => &data.fields,
+        Data::Enum(_) => {
+            return Err(Error::new(
+                Span::call_site(),
+                "`ByteEq` cannot be derived for enums",
+            ))
+        }
+        Data::Union(_) => {
+            return Err(Error::new(
+                Span::call_site(),
+                "`ByteEq` cannot be derived for unions",
+            ))
+        }
+    };
+
+    // The padding check needs concrete field sizes, which generic parameters don't have.
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            "`ByteEq` cannot be derived for generic types",
+        ));
+    }
+
+    let types: Vec<_> = match *fields {
+        Fields::Named(ref f) => f.named.iter().map(|f| &f.ty).collect(),
+        Fields::Unnamed(ref f) => f.unnamed.iter().map(|f| &f.ty).collect(),
+        Fields::Unit => Vec::new(),
+    };
+
+    let field_checks = types.iter().map(|ty| {
+        quote_spanned! {ty.span()=>
+            ::atomic::byte_eq::__private::assert_byte_eq::<#ty>();
+        }
+    });
+
+    let message = format!(
+        "`{}` cannot derive `ByteEq` because it contains padding bytes",
+        name
+    );
+
+    Ok(quote! {
+        unsafe impl ::atomic::ByteEq for #name {}
+
+        const _: () = {
+            #[allow(dead_code)]
+            fn assert_fields_byte_eq() {
+                #(#field_checks)*
+            }
+
+            assert!(
+                ::atomic::byte_eq::__private::size_of::<#name>()
+                    == 0 #(+ ::atomic::byte_eq::__private::size_of::<#types>())*,
+                #message
+            );
+        };
+    })
+}
+
+/// Checks that the struct has the `#[byte_eq(unsafe_assert_structural_eq)]` attribute.
+fn check_acknowledged(input: &DeriveInput) -> Result<(), Error> {
+    let mut acknowledged = false;
+
+    for attr in input.attrs.iter().filter(|a| a.path.is_ident("byte_eq")) {
+        let args = match attr.parse_meta()? {
+            Meta::List(list) => list.nested,
+            meta => {
+                return Err(Error::new(
+                    meta.span(),
+                    "expected `#[byte_eq(unsafe_assert_structural_eq)]`",
+                ))
+            }
+        };
+
+        for arg in args {
+            match arg {
+                NestedMeta::Meta(Meta::Path(ref p))
+                    if p.is_ident("unsafe_assert_structural_eq") =>
+                {
+                    acknowledged = true;
+                }
+                arg => {
+                    return Err(Error::new(
+                        arg.span(),
+                        "unknown `byte_eq` argument, expected `unsafe_assert_structural_eq`",
+                    ))
+                }
+            }
+        }
+    }
+
+    if acknowledged {
+        Ok(())
+    } else {
+        Err(Error::new(
+            input.ident.span(),
+            "deriving `ByteEq` requires `#[byte_eq(unsafe_assert_structural_eq)]`, asserting that \
+             `PartialEq` compares all fields, for example because it is derived",
+        ))
+    }
+}
@@ -0,0 +1,59 @@
+extern crate atomic;
+#[macro_use]
+extern crate atomic_derive;
+
+use atomic::{AtomicCell, ByteEq};
+
+fn is_byte_eq<T: ByteEq>() {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ByteEq)]
+#[byte_eq(unsafe_assert_structural_eq)]
+struct Named {
+    a: u32,
+    b: u16,
+    c: [u8; 2],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ByteEq)]
+#[byte_eq(unsafe_assert_structural_eq)]
+struct Tuple(u32, i32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ByteEq)]
+#[byte_eq(unsafe_assert_structural_eq)]
+struct Unit;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ByteEq)]
+#[byte_eq(unsafe_assert_structural_eq)]
+struct Nested(Tuple, Named, Unit);
+
+#[test]
+fn implements_byte_eq() {
+    is_byte_eq::<Named>();
+    is_byte_eq::<Tuple>();
+    is_byte_eq::<Unit>();
+    is_byte_eq::<Nested>();
+}
+
+#[test]
+fn compare_exchange_bytes() {
+    let a = AtomicCell::new(Tuple(1, -1));
+
+    assert_eq!(
+        a.compare_exchange_bytes(Tuple(1, 1), Tuple(2, 2)),
+        Err(Tuple(1, -1))
+    );
+    assert_eq!(
+        a.compare_exchange_bytes(Tuple(1, -1), Tuple(2, 2)),
+        Ok(Tuple(1, -1))
+    );
+    assert_eq!(a.get(), Tuple(2, 2));
+
+    let n = Named {
+        a: 1,
+        b: 2,
+        c: [3, 4],
+    };
+    let b = AtomicCell::new(n);
+    assert!(b.compare_and_set_bytes(n, Named { a: 5, ..n }));
+    assert_eq!(b.get().a, 5);
+}
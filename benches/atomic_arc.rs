@@ -25,6 +25,21 @@ fn replace(b: &mut test::Bencher) {
     });
 }
 
+#[bench]
+fn replace_many_guards(b: &mut test::Bencher) {
+    // Every replaced value is retired, and with this many slots in use each scan of the slots is
+    // expensive, so this measures how well scanning is amortized over the retired values.
+    let cells: Vec<_> = (0..256).map(|i| AtomicArc::new(Arc::new(i))).collect();
+    let _guards: Vec<_> = cells.iter().map(|c| c.get()).collect();
+
+    let h = AtomicArc::new(Arc::new(777));
+    let a = Cell::new(Some(Arc::new(888)));
+    b.iter(|| {
+        let b = h.replace(a.take().unwrap());
+        a.set(b.clone_inner());
+    });
+}
+
 #[bench]
 fn load(b: &mut test::Bencher) {
     use std::sync::*;
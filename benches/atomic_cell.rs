@@ -88,3 +88,11 @@ fn compare_and_set_usize(b: &mut test::Bencher) {
         i = i.wrapping_add(1);
     });
 }
+
+#[bench]
+fn get_u64x8(b: &mut test::Bencher) {
+    let a = AtomicCell::new([0u64; 8]);
+    let mut sum = 0;
+    b.iter(|| sum += a.get()[0]);
+    test::black_box(sum);
+}
@@ -0,0 +1,36 @@
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// The default number of locks protecting `AtomicCell`s that are not lock-free.
+///
+/// A prime number spreads addresses evenly over the locks.
+const DEFAULT_LOCK_STRIPES: usize = 499;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=ATOMIC_CELL_LOCK_STRIPES");
+
+    let stripes = match env::var("ATOMIC_CELL_LOCK_STRIPES") {
+        Ok(s) => match s.trim().parse::<usize>() {
+            Ok(n) if n > 0 => n,
+            _ => panic!(
+                "ATOMIC_CELL_LOCK_STRIPES must be a positive integer, found `{}`",
+                s
+            ),
+        },
+        Err(_) => DEFAULT_LOCK_STRIPES,
+    };
+
+    let path = Path::new(&env::var("OUT_DIR").unwrap()).join("lock_stripes.rs");
+    let mut file = File::create(path).unwrap();
+    writeln!(file, "/// The number of locks in the global lock table.").unwrap();
+    writeln!(file, "///").unwrap();
+    writeln!(
+        file,
+        "/// Set by `ATOMIC_CELL_LOCK_STRIPES` at build time, {} by default.",
+        DEFAULT_LOCK_STRIPES
+    )
+    .unwrap();
+    writeln!(file, "pub const LOCK_STRIPES: usize = {};", stripes).unwrap();
+}
@@ -0,0 +1,260 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::mem;
+use std::sync::atomic::Ordering;
+
+use atomic_cell::{
+    atomic_compare_exchange, atomic_compare_exchange_weak, atomic_is_lock_free, atomic_load,
+    atomic_store, atomic_swap, check_store_ordering,
+};
+
+/// A generic atomic type with explicit memory orderings.
+///
+/// This type is a lower-level sibling of [`AtomicCell`]: it uses the same atomic instructions or
+/// global locks, but every operation takes an [`Ordering`] argument just like the primitive
+/// atomic types in `std::sync::atomic` do. You can call [`Atomic::<T>::is_lock_free()`] to check
+/// whether atomic instructions or locks will be used.
+///
+/// Compare-and-exchange operations compare values byte-for-byte.
+///
+/// [`AtomicCell`]: ../atomic_cell/struct.AtomicCell.html
+/// [`Ordering`]: https://doc.rust-lang.org/std/sync/atomic/enum.Ordering.html
+/// [`Atomic::<T>::is_lock_free()`]: struct.Atomic.html#method.is_lock_free
+pub struct Atomic<T> {
+    /// The inner value.
+    ///
+    /// If this value can be transmuted into a primitive atomic type, it will be treated as such.
+    /// Otherwise, all potentially concurrent operations on this data will be protected by a global
+    /// lock.
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for Atomic<T> {}
+
+impl<T> Atomic<T> {
+    /// Creates a new atomic initialized with `val`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::Atomic;
+    ///
+    /// let a = Atomic::new(7);
+    /// ```
+    pub fn new(val: T) -> Atomic<T> {
+        Atomic {
+            value: UnsafeCell::new(val),
+        }
+    }
+
+    /// Returns a raw pointer to the inner value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::Atomic;
+    ///
+    /// let a = Atomic::new(7);
+    /// let ptr = a.as_ptr();
+    /// ```
+    pub fn as_ptr(&self) -> *mut T {
+        self.value.get()
+    }
+
+    /// Returns a mutable reference to the inner value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::Atomic;
+    /// use std::sync::atomic::Ordering::SeqCst;
+    ///
+    /// let mut a = Atomic::new(7);
+    /// *a.get_mut() += 1;
+    ///
+    /// assert_eq!(a.load(SeqCst), 8);
+    /// ```
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.value.get() }
+    }
+
+    /// Unwraps the atomic and returns its inner value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::Atomic;
+    ///
+    /// let a = Atomic::new(7);
+    /// let v = a.into_inner();
+    ///
+    /// assert_eq!(v, 7);
+    /// ```
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    /// Returns `true` if operations on values of this type are lock-free.
+    ///
+    /// If the compiler or the platform doesn't support the necessary atomic instructions,
+    /// `Atomic<T>` will use global locks for every potentially concurrent atomic operation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::Atomic;
+    ///
+    /// assert_eq!(Atomic::<usize>::is_lock_free(), true);
+    /// assert_eq!(Atomic::<[u8; 1000]>::is_lock_free(), false);
+    /// ```
+    pub fn is_lock_free() -> bool {
+        atomic_is_lock_free::<T>()
+    }
+
+    /// Stores `val` into the atomic.
+    ///
+    /// If `T` has a destructor, the previous value is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::Atomic;
+    /// use std::sync::atomic::Ordering::{Acquire, Release};
+    ///
+    /// let a = Atomic::new(7);
+    ///
+    /// a.store(8, Release);
+    /// assert_eq!(a.load(Acquire), 8);
+    /// ```
+    pub fn store(&self, val: T, order: Ordering) {
+        if mem::needs_drop::<T>() {
+            check_store_ordering(order);
+            drop(self.swap(val, order));
+        } else {
+            unsafe {
+                atomic_store(self.value.get(), val, order);
+            }
+        }
+    }
+
+    /// Stores `val` into the atomic and returns the previous value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::Atomic;
+    /// use std::sync::atomic::Ordering::AcqRel;
+    ///
+    /// let a = Atomic::new(7);
+    ///
+    /// assert_eq!(a.swap(8, AcqRel), 7);
+    /// assert_eq!(a.into_inner(), 8);
+    /// ```
+    pub fn swap(&self, val: T, order: Ordering) -> T {
+        unsafe { atomic_swap(self.value.get(), val, order) }
+    }
+}
+
+impl<T: Copy> Atomic<T> {
+    /// Loads a copy of the inner value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::Atomic;
+    /// use std::sync::atomic::Ordering::Acquire;
+    ///
+    /// let a = Atomic::new(7);
+    ///
+    /// assert_eq!(a.load(Acquire), 7);
+    /// ```
+    pub fn load(&self, order: Ordering) -> T {
+        unsafe { atomic_load(self.value.get(), order) }
+    }
+
+    /// If the current value is equal to `current` byte-for-byte, stores `new` into the atomic.
+    ///
+    /// The return value is a result indicating whether the new value was written and containing
+    /// the previous value. On success this value is guaranteed to be equal to `current`.
+    ///
+    /// `success` describes the memory ordering of the read-modify-write operation that takes
+    /// place if the comparison succeeds, and `failure` describes the ordering of the load that
+    /// takes place if it fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::Atomic;
+    /// use std::sync::atomic::Ordering::{AcqRel, Acquire};
+    ///
+    /// let a = Atomic::new(7);
+    ///
+    /// assert_eq!(a.compare_exchange(1, 8, AcqRel, Acquire), Err(7));
+    /// assert_eq!(a.compare_exchange(7, 8, AcqRel, Acquire), Ok(7));
+    /// assert_eq!(a.load(Acquire), 8);
+    /// ```
+    pub fn compare_exchange(
+        &self,
+        current: T,
+        new: T,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<T, T> {
+        unsafe { atomic_compare_exchange(self.value.get(), current, new, success, failure) }
+    }
+
+    /// If the current value is equal to `current` byte-for-byte, stores `new` into the atomic.
+    ///
+    /// Unlike [`compare_exchange`], this method is allowed to spuriously fail even when the
+    /// comparison succeeds, which can result in more efficient code on some platforms. It is
+    /// meant to be used in a loop.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::Atomic;
+    /// use std::sync::atomic::Ordering::{Relaxed, Release};
+    ///
+    /// let a = Atomic::new(7);
+    ///
+    /// let mut current = a.load(Relaxed);
+    /// loop {
+    ///     match a.compare_exchange_weak(current, current * 2, Release, Relaxed) {
+    ///         Ok(_) => break,
+    ///         Err(x) => current = x,
+    ///     }
+    /// }
+    /// assert_eq!(a.load(Relaxed), 14);
+    /// ```
+    ///
+    /// [`compare_exchange`]: struct.Atomic.html#method.compare_exchange
+    pub fn compare_exchange_weak(
+        &self,
+        current: T,
+        new: T,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<T, T> {
+        unsafe { atomic_compare_exchange_weak(self.value.get(), current, new, success, failure) }
+    }
+}
+
+impl<T: Default> Default for Atomic<T> {
+    fn default() -> Atomic<T> {
+        Atomic::new(T::default())
+    }
+}
+
+impl<T> From<T> for Atomic<T> {
+    fn from(val: T) -> Atomic<T> {
+        Atomic::new(val)
+    }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for Atomic<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Atomic")
+            .field("value", &self.load(Ordering::SeqCst))
+            .finish()
+    }
+}
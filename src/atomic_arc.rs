@@ -6,9 +6,6 @@ use std::sync::atomic::{self, AtomicUsize, AtomicPtr, Ordering};
 
 use hazard;
 
-// TODO: ZSV are all allocated at address 0x1 - what about transfering drop resp.?
-// - maybe we should for ZST in destroy_object() and always destroy?
-
 pub struct AtomicArc<T> {
     object: AtomicPtr<T>,
     _marker: PhantomData<Option<Arc<T>>>,
@@ -47,6 +44,16 @@ impl<T> AtomicArc<T> {
         let mut object = self.object.load(Ordering::Relaxed);
 
         loop {
+            if object.is_null() {
+                // There is nothing to protect. A slot holding zero would look free and could be
+                // handed out to another guard while this one still owns it.
+                return SharedArc {
+                    object,
+                    slot: ptr::null(),
+                    _marker: PhantomData,
+                };
+            }
+
             if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
                 // HACK(stjepang): On x86 architectures there are two different ways of executing a
                 // `SeqCst` fence.
@@ -76,7 +83,7 @@ impl<T> AtomicArc<T> {
             }
             object = new;
 
-            // Deallocate the slot, potentially destroying the protected object.
+            // Release the slot before protecting the new object.
             drop(shared);
         }
     }
@@ -128,18 +135,10 @@ impl<T> AtomicArc<T> {
 
 impl<T> Drop for AtomicArc<T> {
     fn drop(&mut self) {
-        // 1) Either somebody is holding a reference to this element and we want to move
-        //    responsibility of calling a drop(T) to them.
-        // 2) Nobody is holding a reference to this element, therefore we are in charge of dropping
-        //    an element.
-
+        // Readers might still be holding the current value, so it is retired just like a
+        // displaced value.
         let obj = self.object.load(Ordering::Relaxed);
-
-        if hazard::registry().destroy_object(obj as usize) {
-            unsafe {
-                drop(Arc::from_raw(obj));
-            }
-        }
+        unsafe { hazard::retire(obj, drop_arc::<T>) }
     }
 }
 
@@ -191,27 +190,14 @@ impl<T> SharedArc<T> {
 impl<T> Drop for SharedArc<T> {
     #[inline]
     fn drop(&mut self) {
-        // Set the slot back to zero. If it has been modified, that means we've been notified that
-        // the object must be destroyed and it's our responsibility to do so.
-        //
-        // If the slot is not even allocatedt, this `SharedArc` was implicitly responsible for
-        // destroying the object from the start.
-        if let Some(slot) = self.slot() {
-            if slot.swap(0, Ordering::Acquire) == self.object as usize {
-                // Nothing to do! This is the common case.
-                return;
-            }
-        }
+        match self.slot() {
+            // Stop protecting the object. This is the common case.
+            Some(slot) => slot.store(0, Ordering::Release),
 
-        #[cold]
-        fn destroy<T>(object: *const T) {
-            if hazard::registry().destroy_object(object as usize) {
-                unsafe {
-                    drop(Arc::from_raw(object));
-                }
-            }
+            // This `SharedArc` holds an object displaced from the `AtomicArc`, and is responsible
+            // for dropping it once no reader protects it anymore.
+            None => unsafe { hazard::retire(self.object, drop_arc::<T>) },
         }
-        destroy(self.object)
     }
 }
 
@@ -227,6 +213,10 @@ impl<'a, T> Into<Option<Arc<T>>> for &'a SharedArc<T> {
     }
 }
 
+unsafe fn drop_arc<T>(ptr: *mut T) {
+    drop(Arc::from_raw(ptr));
+}
+
 fn into_raw<T, U>(val: U) -> *mut T
 where
     U: Into<Option<Arc<T>>>,
@@ -0,0 +1,331 @@
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{self, AtomicPtr, AtomicUsize, Ordering};
+
+use hazard;
+
+/// An atomic pointer to an optional, uniquely owned `Box<T>`.
+///
+/// Readers get a [`SharedBox`] that keeps the value alive through a hazard pointer, so values
+/// can be replaced concurrently without reference counting. A displaced value is dropped some
+/// time after the last reader that has seen it is done with it, see [`hazard`] for details.
+///
+/// [`SharedBox`]: struct.SharedBox.html
+/// [`hazard`]: ../hazard/index.html
+pub struct AtomicBox<T> {
+    object: AtomicPtr<T>,
+    _marker: PhantomData<Option<Box<T>>>,
+}
+
+unsafe impl<T: Send + Sync> Send for AtomicBox<T> {}
+unsafe impl<T: Send + Sync> Sync for AtomicBox<T> {}
+
+impl<T> AtomicBox<T> {
+    /// Creates a new atomic box initialized with `val`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicBox;
+    ///
+    /// let a = AtomicBox::new(Box::new(7));
+    /// let b = AtomicBox::<i32>::new(None);
+    /// ```
+    pub fn new<U>(val: U) -> AtomicBox<T>
+    where
+        U: Into<Option<Box<T>>>,
+    {
+        AtomicBox {
+            object: AtomicPtr::new(into_raw(val)),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Unwraps the atomic box and returns its inner value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicBox;
+    ///
+    /// let a = AtomicBox::new(Box::new(7));
+    ///
+    /// assert_eq!(a.into_inner(), Some(Box::new(7)));
+    /// ```
+    ///
+    /// Guards returned by [`get`] borrow the atomic box, so it can't be unwrapped while the value
+    /// is still protected:
+    ///
+    /// ```compile_fail
+    /// use atomic::AtomicBox;
+    ///
+    /// let a = AtomicBox::new(Box::new(7));
+    /// let s = a.get();
+    ///
+    /// drop(a.into_inner());
+    /// assert_eq!(s.as_ref(), Some(&7));
+    /// ```
+    ///
+    /// [`get`]: struct.AtomicBox.html#method.get
+    pub fn into_inner(self) -> Option<Box<T>> {
+        let raw = self.object.load(Ordering::Relaxed);
+        mem::forget(self);
+
+        if raw.is_null() {
+            None
+        } else {
+            unsafe { Some(Box::from_raw(raw)) }
+        }
+    }
+
+    /// Returns a guard protecting the current value from being dropped.
+    ///
+    /// The guard borrows the atomic box, so the value can't be taken out with [`into_inner`]
+    /// while it is protected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicBox;
+    ///
+    /// let a = AtomicBox::new(Box::new(7));
+    /// let s = a.get();
+    ///
+    /// a.set(Box::new(8));
+    /// assert_eq!(s.as_ref(), Some(&7));
+    /// assert_eq!(a.get().as_ref(), Some(&8));
+    /// ```
+    ///
+    /// [`into_inner`]: struct.AtomicBox.html#method.into_inner
+    pub fn get(&self) -> SharedBox<'_, T> {
+        let slot = unsafe { &*hazard::allocate_slot() };
+
+        let mut object = self.object.load(Ordering::Relaxed);
+
+        loop {
+            if object.is_null() {
+                // There is nothing to protect. A slot holding zero would look free and could be
+                // handed out to another guard while this one still owns it.
+                return SharedBox {
+                    object,
+                    slot: ptr::null(),
+                    _marker: PhantomData,
+                };
+            }
+
+            slot.store(object as usize, Ordering::Relaxed);
+            atomic::fence(Ordering::SeqCst);
+
+            let shared = SharedBox {
+                object,
+                slot,
+                _marker: PhantomData,
+            };
+
+            let new = self.object.load(Ordering::Relaxed);
+            if new == object {
+                return shared;
+            }
+            object = new;
+
+            // Release the slot before protecting the new object.
+            drop(shared);
+        }
+    }
+
+    /// Stores `val` into the atomic box and returns the previous value.
+    ///
+    /// The previous value is dropped after the returned [`SharedBox`] and all other guards
+    /// protecting it are dropped, see [`hazard`] for details. The returned guard owns the value,
+    /// so it doesn't borrow the atomic box.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicBox;
+    ///
+    /// let a = AtomicBox::new(Box::new(7));
+    ///
+    /// assert_eq!(a.replace(Box::new(8)).as_ref(), Some(&7));
+    /// assert_eq!(a.replace(None).as_ref(), Some(&8));
+    /// assert_eq!(a.get().as_ref(), None);
+    /// ```
+    ///
+    /// [`SharedBox`]: struct.SharedBox.html
+    /// [`hazard`]: ../hazard/index.html
+    pub fn replace<U>(&self, val: U) -> SharedBox<'static, T>
+    where
+        U: Into<Option<Box<T>>>,
+    {
+        let old = self.object.swap(into_raw(val), Ordering::SeqCst);
+        SharedBox {
+            object: old,
+            slot: ptr::null(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Stores `val` into the atomic box.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicBox;
+    ///
+    /// let a = AtomicBox::new(Box::new(7));
+    /// a.set(Box::new(8));
+    ///
+    /// assert_eq!(a.get().as_ref(), Some(&8));
+    /// ```
+    pub fn set<U>(&self, val: U)
+    where
+        U: Into<Option<Box<T>>>,
+    {
+        self.replace(val);
+    }
+
+    /// Takes the value out of the atomic box, leaving it empty.
+    ///
+    /// Note that `atomic_box.take()` is equivalent to:
+    ///
+    /// ```ignore
+    /// atomic_box.replace(None)
+    /// ```
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicBox;
+    ///
+    /// let a = AtomicBox::new(Box::new(7));
+    ///
+    /// assert_eq!(a.take().as_ref(), Some(&7));
+    /// assert_eq!(a.take().as_ref(), None);
+    /// ```
+    pub fn take(&self) -> SharedBox<'static, T> {
+        self.replace(None)
+    }
+
+    /// If the current value is the one protected by `current`, stores `new` into the atomic box.
+    ///
+    /// On failure, `new` is given back.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicBox;
+    ///
+    /// let a = AtomicBox::new(Box::new(7));
+    /// let s = a.get();
+    ///
+    /// assert!(a.compare_and_set(&s, Box::new(8)).is_ok());
+    /// assert_eq!(a.compare_and_set(&s, Box::new(9)), Err(Some(Box::new(9))));
+    /// assert_eq!(a.get().as_ref(), Some(&8));
+    /// ```
+    pub fn compare_and_set<U>(
+        &self,
+        current: &SharedBox<'_, T>,
+        new: U,
+    ) -> Result<(), Option<Box<T>>>
+    where
+        U: Into<Option<Box<T>>>,
+    {
+        let new = into_raw(new);
+        let old = current.object;
+
+        match self
+            .object
+            .compare_exchange(old, new, Ordering::SeqCst, Ordering::SeqCst)
+        {
+            Ok(_) => {
+                drop(SharedBox {
+                    object: old,
+                    slot: ptr::null(),
+                    _marker: PhantomData,
+                });
+                Ok(())
+            }
+            Err(_) => {
+                if new.is_null() {
+                    Err(None)
+                } else {
+                    unsafe { Err(Some(Box::from_raw(new))) }
+                }
+            }
+        }
+    }
+}
+
+impl<T> Drop for AtomicBox<T> {
+    fn drop(&mut self) {
+        // Guards protecting the current value borrow the atomic box, so none of them are left.
+        let obj = self.object.load(Ordering::Relaxed);
+        if !obj.is_null() {
+            unsafe { drop_box(obj) }
+        }
+    }
+}
+
+impl<T, U> From<U> for AtomicBox<T>
+where
+    U: Into<Option<Box<T>>>,
+{
+    fn from(val: U) -> AtomicBox<T> {
+        AtomicBox::new(val)
+    }
+}
+
+/// A value loaded from or displaced out of an [`AtomicBox`].
+///
+/// The value is guaranteed not to be dropped for as long as this guard is alive.
+///
+/// A guard loaded with [`get`] borrows the atomic box for `'a`. A guard holding a displaced value
+/// owns it, and is `SharedBox<'static, T>`.
+///
+/// [`AtomicBox`]: struct.AtomicBox.html
+/// [`get`]: struct.AtomicBox.html#method.get
+pub struct SharedBox<'a, T> {
+    object: *mut T,
+    slot: *const AtomicUsize,
+    _marker: PhantomData<(&'a (), Option<Box<T>>)>,
+}
+
+impl<'a, T> SharedBox<'a, T> {
+    /// Returns a reference to the protected value, or `None` if the atomic box was empty.
+    pub fn as_ref(&self) -> Option<&T> {
+        unsafe { self.object.as_ref() }
+    }
+
+    fn slot(&self) -> Option<&AtomicUsize> {
+        unsafe { self.slot.as_ref() }
+    }
+}
+
+impl<'a, T> Drop for SharedBox<'a, T> {
+    #[inline]
+    fn drop(&mut self) {
+        match self.slot() {
+            // Stop protecting the value.
+            Some(slot) => slot.store(0, Ordering::Release),
+
+            // This `SharedBox` holds a value displaced from the `AtomicBox`, and is responsible
+            // for dropping it once no reader protects it anymore.
+            None => unsafe { hazard::retire(self.object, drop_box::<T>) },
+        }
+    }
+}
+
+unsafe fn drop_box<T>(ptr: *mut T) {
+    drop(Box::from_raw(ptr));
+}
+
+fn into_raw<T, U>(val: U) -> *mut T
+where
+    U: Into<Option<Box<T>>>,
+{
+    match val.into() {
+        None => ptr::null_mut(),
+        Some(val) => Box::into_raw(val),
+    }
+}
@@ -1,12 +1,17 @@
 use std::cell::UnsafeCell;
 use std::fmt;
-use std::mem;
+use std::hint;
+use std::mem::{self, MaybeUninit};
 use std::ptr;
 use std::slice;
 use std::sync::atomic::{self, AtomicBool, Ordering};
+#[cfg(feature = "use_std")]
+use std::sync::Arc;
+#[cfg(feature = "use_std")]
+use std::time::{Duration, Instant};
 
-// TODO: fn try_set(), which tries to insert a Box into AtomicCell<Option<Box<T>>
-// TODO: maybe a bunch of functions for AtomicCell<Option<_>>?
+use byte_eq::ByteEq;
+use double_word::AtomicDoubleWord;
 
 /// A thread-safe mutable memory location.
 ///
@@ -16,8 +21,25 @@ use std::sync::atomic::{self, AtomicBool, Ordering};
 /// global locks otherwise. You can call [`AtomicCell::<T>::is_lock_free()`] to check whether
 /// atomic instructions or locks will be used.
 ///
+/// The global locks form a table indexed by the address of the cell. Its size defaults to 499 and
+/// can be changed by setting the `ATOMIC_CELL_LOCK_STRIPES` environment variable when building
+/// this crate. The locks are sequence locks, so [`get`] doesn't block other readers and is only
+/// retried if a writer interfered.
+///
+/// Threads waiting for a lock spin for a while and then keep yielding to the scheduler. With the
+/// `parking` feature, they are put to sleep instead and woken up when the lock is released, which
+/// avoids burning CPU when there are more threads than cores.
+///
+/// Values of 16 bytes that are aligned to 16 bytes use a 16-byte compare-and-swap if the CPU has
+/// one, which is `cmpxchg16b` on x86_64 and `ldaxp`/`stlxp` on aarch64. Wrap a value in
+/// [`Align16`] to align it. Every operation on such a value is sequentially consistent, whatever
+/// ordering is passed to methods like [`load_with`].
+///
 /// [`Cell`]: https://doc.rust-lang.org/std/cell/struct.Cell.html
 /// [`AtomicCell::<T>::is_lock_free()`]: struct.AtomicCell.html#method.is_lock_free
+/// [`get`]: struct.AtomicCell.html#method.get
+/// [`Align16`]: struct.Align16.html
+/// [`load_with`]: struct.AtomicCell.html#method.load_with
 pub struct AtomicCell<T> {
     /// The inner value.
     ///
@@ -27,6 +49,12 @@ pub struct AtomicCell<T> {
     value: UnsafeCell<T>,
 }
 
+// `AtomicCell` never hands out references to its value: the value is only ever copied in or
+// out, swapped or replaced as a whole, either with atomic instructions or while holding a lock.
+// Sharing a cell between threads therefore amounts to sending values of `T` between them, so
+// `T: Send` is enough, just like for `Mutex<T>`.
+unsafe impl<T: Send> Sync for AtomicCell<T> {}
+
 impl<T> AtomicCell<T> {
     /// Creates a new atomic cell initialized with `val`.
     ///
@@ -113,6 +141,14 @@ impl<T> AtomicCell<T> {
     /// // Operations on zero-sized types are always lock-free.
     /// assert_eq!(AtomicCell::<()>::is_lock_free(), true);
     ///
+    /// // Narrower integer types have primitive atomic types, too.
+    /// assert_eq!(AtomicCell::<u8>::is_lock_free(), true);
+    ///
+    /// // A pair of `usize`s is not aligned to its size, so only the aligned version can use a
+    /// // 16-byte compare-and-swap, if the CPU has one.
+    /// # #[cfg(target_pointer_width = "64")]
+    /// assert_eq!(AtomicCell::<(usize, usize)>::is_lock_free(), false);
+    ///
     /// // Very large types cannot be represented as any of the standard atomic types, so atomic
     /// // operations on them will have to use global locks for synchronization.
     /// assert_eq!(AtomicCell::<[u8; 1000]>::is_lock_free(), false);
@@ -135,11 +171,36 @@ impl<T> AtomicCell<T> {
     /// assert_eq!(a.get(), 8);
     /// ```
     pub fn set(&self, val: T) {
+        self.store_with(val, Ordering::SeqCst);
+    }
+
+    /// Stores `val` into the atomic cell using the given memory ordering.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `order` is [`Acquire`] or [`AcqRel`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    /// use std::sync::atomic::Ordering::Release;
+    ///
+    /// let a = AtomicCell::new(7);
+    ///
+    /// a.store_with(8, Release);
+    /// assert_eq!(a.get(), 8);
+    /// ```
+    ///
+    /// [`Acquire`]: https://doc.rust-lang.org/std/sync/atomic/enum.Ordering.html#variant.Acquire
+    /// [`AcqRel`]: https://doc.rust-lang.org/std/sync/atomic/enum.Ordering.html#variant.AcqRel
+    pub fn store_with(&self, val: T, order: Ordering) {
         if mem::needs_drop::<T>() {
-            drop(self.replace(val));
+            check_store_ordering(order);
+            drop(self.swap_with(val, order));
         } else {
             unsafe {
-                atomic_store(self.value.get(), val);
+                atomic_store(self.value.get(), val, order);
             }
         }
     }
@@ -158,7 +219,25 @@ impl<T> AtomicCell<T> {
     /// assert_eq!(a.get(), 8);
     /// ```
     pub fn replace(&self, val: T) -> T {
-        unsafe { atomic_swap(self.value.get(), val) }
+        self.swap_with(val, Ordering::SeqCst)
+    }
+
+    /// Stores `val` into the atomic cell using the given memory ordering and returns the previous
+    /// value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    /// use std::sync::atomic::Ordering::AcqRel;
+    ///
+    /// let a = AtomicCell::new(7);
+    ///
+    /// assert_eq!(a.swap_with(8, AcqRel), 7);
+    /// assert_eq!(a.get(), 8);
+    /// ```
+    pub fn swap_with(&self, val: T, order: Ordering) -> T {
+        unsafe { atomic_swap(self.value.get(), val, order) }
     }
 }
 
@@ -200,7 +279,30 @@ impl<T: Copy> AtomicCell<T> {
     /// assert_eq!(a.get(), 7);
     /// ```
     pub fn get(&self) -> T {
-        unsafe { atomic_load(self.value.get()) }
+        self.load_with(Ordering::SeqCst)
+    }
+
+    /// Returns a copy of the inner value, loaded using the given memory ordering.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `order` is [`Release`] or [`AcqRel`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    /// use std::sync::atomic::Ordering::Acquire;
+    ///
+    /// let a = AtomicCell::new(7);
+    ///
+    /// assert_eq!(a.load_with(Acquire), 7);
+    /// ```
+    ///
+    /// [`Release`]: https://doc.rust-lang.org/std/sync/atomic/enum.Ordering.html#variant.Release
+    /// [`AcqRel`]: https://doc.rust-lang.org/std/sync/atomic/enum.Ordering.html#variant.AcqRel
+    pub fn load_with(&self, order: Ordering) -> T {
+        unsafe { atomic_load(self.value.get(), order) }
     }
 
     /// Updates the inner value using a function and returns the new value.
@@ -238,22 +340,72 @@ impl<T: Copy> AtomicCell<T> {
     where
         F: FnMut(T) -> T,
     {
-        let mut current = self.get();
-
-        loop {
-            let new = f(current);
-
-            let previous = unsafe {
-                atomic_compare_and_swap(self.value.get(), current, new)
-            };
-
-            if byte_eq(&previous, &current) {
-                return new;
-            }
+        let result = unsafe {
+            atomic_fetch_update(self.value.get(), Ordering::SeqCst, Ordering::SeqCst, |x| {
+                Some(f(x))
+            })
+        };
 
-            current = previous;
+        match result {
+            Ok((_, new)) => new,
+            Err(_) => unreachable!(),
         }
     }
+
+    /// Fetches the inner value and applies a function to it that optionally returns a new value.
+    ///
+    /// Returns `Ok(previous)` if the function returned `Some(new)` and `new` was stored, and
+    /// `Err(previous)` if the function returned `None`, in which case the atomic cell is left
+    /// untouched.
+    ///
+    /// Function `f` might have to be called multiple times if the inner value is concurrently
+    /// changed by other threads.
+    ///
+    /// Note that `atomic_cell.fetch_update(f)` is equivalent to:
+    ///
+    /// ```ignore
+    /// loop {
+    ///     let current = atomic_cell.get();
+    ///
+    ///     match f(current) {
+    ///         None => break Err(current),
+    ///         Some(new) => {
+    ///             if atomic_cell.compare_and_set(current, new) {
+    ///                 break Ok(current);
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    ///
+    /// // Increment only if below the limit.
+    /// let a = AtomicCell::new(8);
+    ///
+    /// assert_eq!(a.fetch_update(|x| if x < 9 { Some(x + 1) } else { None }), Ok(8));
+    /// assert_eq!(a.fetch_update(|x| if x < 9 { Some(x + 1) } else { None }), Err(9));
+    /// assert_eq!(a.get(), 9);
+    ///
+    /// // Set only if currently `None`.
+    /// let b = AtomicCell::new(None);
+    ///
+    /// assert_eq!(b.fetch_update(|x| if x.is_none() { Some(Some(1)) } else { None }), Ok(None));
+    /// assert_eq!(b.fetch_update(|x| if x.is_none() { Some(Some(2)) } else { None }), Err(Some(1)));
+    /// assert_eq!(b.get(), Some(1));
+    /// ```
+    pub fn fetch_update<F>(&self, f: F) -> Result<T, T>
+    where
+        F: FnMut(T) -> Option<T>,
+    {
+        let result = unsafe {
+            atomic_fetch_update(self.value.get(), Ordering::SeqCst, Ordering::SeqCst, f)
+        };
+        result.map(|(previous, _)| previous)
+    }
 }
 
 impl<T: Copy + Eq> AtomicCell<T> {
@@ -274,182 +426,1435 @@ impl<T: Copy + Eq> AtomicCell<T> {
     /// assert_eq!(a.compare_and_set(7, 8), true);
     /// assert_eq!(a.get(), 8);
     /// ```
-    pub fn compare_and_set(&self, mut current: T, new: T) -> bool {
+    pub fn compare_and_set(&self, current: T, new: T) -> bool {
+        self.compare_exchange(current, new).is_ok()
+    }
+
+    /// If the current value equals `current`, stores `new` into the atomic cell.
+    ///
+    /// The return value is a result indicating whether the new value was written and containing
+    /// the previous value. On success this value is guaranteed to be equal to `current`. On
+    /// failure it is the value that was observed in the atomic cell instead, so there is no need to
+    /// call [`get`] again.
+    ///
+    /// # Padding
+    ///
+    /// Compare-and-swap instructions compare bytes, but a value can be equal to `current` while
+    /// its bytes differ, for example because `T` has padding bytes. When that happens, the
+    /// operation is retried against the exact bytes found in the cell, so it succeeds as long as
+    /// the value stays equal to `current`.
+    ///
+    /// Normalizing the padding bytes instead is not possible in general, since their positions
+    /// aren't known for an arbitrary `T` and every copy of a value may change them. Types without
+    /// padding can implement [`ByteEq`] and use [`compare_exchange_bytes`], which never retries.
+    ///
+    /// A retry only happens when the first comparison fails because of padding, or when another
+    /// thread has changed the bytes of the cell since they were observed. Each retry beyond the
+    /// first therefore means that another operation on the cell has completed, so this method is
+    /// lock-free, just like a loop around [`compare_exchange_weak`]. It is not wait-free: a steady
+    /// stream of writes that only change the padding bytes can keep delaying it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    ///
+    /// let a = AtomicCell::new(7);
+    ///
+    /// assert_eq!(a.compare_exchange(1, 8), Err(7));
+    /// assert_eq!(a.get(), 7);
+    ///
+    /// assert_eq!(a.compare_exchange(7, 8), Ok(7));
+    /// assert_eq!(a.get(), 8);
+    /// ```
+    ///
+    /// [`get`]: struct.AtomicCell.html#method.get
+    /// [`ByteEq`]: ../byte_eq/trait.ByteEq.html
+    /// [`compare_exchange_bytes`]: struct.AtomicCell.html#method.compare_exchange_bytes
+    /// [`compare_exchange_weak`]: struct.AtomicCell.html#method.compare_exchange_weak
+    pub fn compare_exchange(&self, current: T, new: T) -> Result<T, T> {
+        self.compare_exchange_with(current, new, Ordering::SeqCst, Ordering::SeqCst)
+    }
+
+    /// If the current value equals `current`, stores `new` into the atomic cell using the given
+    /// memory orderings.
+    ///
+    /// This is [`compare_exchange`] with explicit orderings: `success` describes the memory
+    /// ordering of the read-modify-write operation that takes place if the comparison succeeds,
+    /// and `failure` describes the ordering of the load that takes place if it fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `failure` is [`Release`] or [`AcqRel`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    /// use std::sync::atomic::Ordering::{AcqRel, Acquire};
+    ///
+    /// let a = AtomicCell::new(7);
+    ///
+    /// assert_eq!(a.compare_exchange_with(1, 8, AcqRel, Acquire), Err(7));
+    /// assert_eq!(a.compare_exchange_with(7, 8, AcqRel, Acquire), Ok(7));
+    /// assert_eq!(a.get(), 8);
+    /// ```
+    ///
+    /// [`compare_exchange`]: struct.AtomicCell.html#method.compare_exchange
+    /// [`Release`]: https://doc.rust-lang.org/std/sync/atomic/enum.Ordering.html#variant.Release
+    /// [`AcqRel`]: https://doc.rust-lang.org/std/sync/atomic/enum.Ordering.html#variant.AcqRel
+    pub fn compare_exchange_with(
+        &self,
+        current: T,
+        new: T,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<T, T> {
+        let mut raw = MaybeUninit::new(current);
+
         loop {
-            let previous = unsafe {
-                atomic_compare_and_swap(self.value.get(), current, new)
+            let ok = unsafe {
+                atomic_compare_exchange_raw(
+                    self.value.get(),
+                    &mut raw,
+                    new,
+                    success,
+                    failure,
+                    false,
+                )
             };
-
-            if byte_eq(&previous, &current) {
-                return true;
+            let previous = unsafe { ptr::read(raw.as_ptr()) };
+            if ok {
+                return Ok(previous);
             }
 
             if previous != current {
-                return false;
+                return Err(previous);
             }
 
-            // Since `byte_eq(&previous, &current)` is `false`, that means the compare-and-swap
-            // operation failed and didn't store `new`. However, `previous == current`, which means
-            // it technically should've succeeded.
+            // The bytes differ, so the compare-and-swap operation failed and didn't store `new`.
+            // However, the values are equal, which means it technically should've succeeded. This
+            // happens when `T` has padding bytes or several representations of the same value.
             //
-            // We cannot return neither `true` nor `false` here because the operation didn't
-            // succeed nor fail, but simply encountered an inconsistent state. The only option left
-            // is to retry with `previous` as the new `current`.
-            current = previous;
+            // We cannot return neither `Ok` nor `Err` here because the operation didn't succeed
+            // nor fail, but simply encountered an inconsistent state. The only option left is to
+            // retry, comparing against the exact bytes that were observed this time. Those are now
+            // in `raw`, and copying them into a `T` wouldn't preserve the padding bytes.
+            //
+            // The next attempt can only fail if another thread has written to the cell since
+            // those bytes were observed, so every further retry is paid for by a completed write.
         }
     }
-}
 
-macro_rules! impl_arithmetic {
-    ($t:ty, $example:tt) => {
-        impl AtomicCell<$t> {
-            /// Increments the inner value by `val` and returns the new value.
-            ///
-            /// The addition wraps on overflow. Note that `atomic_cell.add(val)` is equivalent to:
-            ///
-            /// ```ignore
-            /// atomic_cell.update(|x| x.wrapping_add(val))
-            /// ```
-            ///
-            /// # Examples
-            ///
-            /// ```
-            /// use atomic::AtomicCell;
-            ///
-            #[doc = $example]
-            ///
-            /// assert_eq!(a.add(3), 10);
-            /// assert_eq!(a.get(), 10);
-            /// ```
-            #[inline]
-            pub fn add(&self, val: $t) -> $t {
-                if can_transmute::<$t, atomic::AtomicUsize>() {
-                    let a = unsafe { &*(self.value.get() as *const atomic::AtomicUsize) };
-                    a.fetch_add(val as usize, Ordering::SeqCst).wrapping_add(val as usize) as $t
-                } else {
-                    let _lock = lock(self.value.get() as usize);
-                    let value = unsafe { &mut *(self.value.get()) };
-                    *value = value.wrapping_add(val);
-                    *value
-                }
-            }
+    /// If the current value equals `current`, stores `new` into the atomic cell.
+    ///
+    /// Unlike [`compare_exchange`], this method is allowed to spuriously fail even when the
+    /// current value equals `current`, which can result in more efficient code on some platforms.
+    /// It is meant to be used in a loop.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    ///
+    /// let a = AtomicCell::new(7);
+    ///
+    /// let mut current = a.get();
+    /// loop {
+    ///     match a.compare_exchange_weak(current, current * 2) {
+    ///         Ok(_) => break,
+    ///         Err(x) => current = x,
+    ///     }
+    /// }
+    /// assert_eq!(a.get(), 14);
+    /// ```
+    ///
+    /// [`compare_exchange`]: struct.AtomicCell.html#method.compare_exchange
+    pub fn compare_exchange_weak(&self, current: T, new: T) -> Result<T, T> {
+        let mut raw = MaybeUninit::new(current);
 
-            /// Decrements the inner value by `val` and returns the new value.
-            ///
-            /// The subtraction wraps on overflow. Note that `atomic_cell.sub(val)` is equivalent
-            /// to:
-            ///
-            /// ```ignore
-            /// atomic_cell.update(|x| x.wrapping_sub(val))
-            /// ```
-            ///
-            /// # Examples
-            ///
-            /// ```
-            /// use atomic::AtomicCell;
-            ///
-            #[doc = $example]
-            ///
-            /// assert_eq!(a.sub(3), 4);
-            /// assert_eq!(a.get(), 4);
-            /// ```
-            #[inline]
-            pub fn sub(&self, val: $t) -> $t {
-                if can_transmute::<$t, atomic::AtomicUsize>() {
-                    let a = unsafe { &*(self.value.get() as *const atomic::AtomicUsize) };
-                    a.fetch_sub(val as usize, Ordering::SeqCst).wrapping_sub(val as usize) as $t
-                } else {
-                    let _lock = lock(self.value.get() as usize);
-                    let value = unsafe { &mut *(self.value.get()) };
-                    *value = value.wrapping_sub(val);
-                    *value
-                }
-            }
-        }
-    };
-    ($t:ty, $atomic:ty, $example:tt) => {
-        impl AtomicCell<$t> {
-            /// Increments the inner value by `val` and returns the new value.
-            ///
-            /// The addition wraps on overflow. Note that `atomic_cell.add(val)` is equivalent to:
-            ///
-            /// ```ignore
-            /// atomic_cell.update(|x| x.wrapping_add(val))
-            /// ```
-            ///
-            /// # Examples
-            ///
-            /// ```
-            /// use atomic::AtomicCell;
-            ///
-            #[doc = $example]
-            ///
-            /// assert_eq!(a.add(3), 10);
-            /// assert_eq!(a.get(), 10);
-            /// ```
-            #[inline]
-            pub fn add(&self, val: $t) -> $t {
-                let a = unsafe { &*(self.value.get() as *const $atomic) };
-                a.fetch_add(val, Ordering::SeqCst).wrapping_add(val)
+        loop {
+            let ok = unsafe {
+                atomic_compare_exchange_raw(
+                    self.value.get(),
+                    &mut raw,
+                    new,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                    true,
+                )
+            };
+            let previous = unsafe { ptr::read(raw.as_ptr()) };
+            if ok {
+                return Ok(previous);
             }
 
-            /// Decrements the inner value by `val` and returns the new value.
-            ///
-            /// The subtraction wraps on overflow. Note that `atomic_cell.sub(val)` is equivalent
-            /// to:
-            ///
-            /// ```ignore
-            /// atomic_cell.update(|x| x.wrapping_sub(val))
-            /// ```
-            ///
-            /// # Examples
-            ///
-            /// ```
-            /// use atomic::AtomicCell;
-            ///
-            #[doc = $example]
-            ///
-            /// assert_eq!(a.sub(3), 4);
-            /// assert_eq!(a.get(), 4);
-            /// ```
-            #[inline]
-            pub fn sub(&self, val: $t) -> $t {
-                let a = unsafe { &*(self.value.get() as *const $atomic) };
-                a.fetch_sub(val, Ordering::SeqCst).wrapping_sub(val)
+            if previous != current {
+                return Err(previous);
             }
-        }
-    };
-}
 
-cfg_if! {
-    if #[cfg(feature = "nightly")] {
-        impl_arithmetic!(u8, atomic::AtomicU8, "let a = AtomicCell::new(7u8);");
-        impl_arithmetic!(i8, atomic::AtomicI8, "let a = AtomicCell::new(7i8);");
-        impl_arithmetic!(u16, atomic::AtomicU16, "let a = AtomicCell::new(7u16);");
-        impl_arithmetic!(i16, atomic::AtomicI16, "let a = AtomicCell::new(7i16);");
-        impl_arithmetic!(u32, atomic::AtomicU32, "let a = AtomicCell::new(7u32);");
-        impl_arithmetic!(i32, atomic::AtomicI32, "let a = AtomicCell::new(7i32);");
-        impl_arithmetic!(u64, atomic::AtomicU64, "let a = AtomicCell::new(7u64);");
-        impl_arithmetic!(i64, atomic::AtomicI64, "let a = AtomicCell::new(7i64);");
-    } else {
-        impl_arithmetic!(u8, "let a = AtomicCell::new(7u8);");
-        impl_arithmetic!(i8, "let a = AtomicCell::new(7i8);");
-        impl_arithmetic!(u16, "let a = AtomicCell::new(7u16);");
-        impl_arithmetic!(i16, "let a = AtomicCell::new(7i16);");
-        impl_arithmetic!(u32, "let a = AtomicCell::new(7u32);");
-        impl_arithmetic!(i32, "let a = AtomicCell::new(7i32);");
-        impl_arithmetic!(u64, "let a = AtomicCell::new(7u64);");
-        impl_arithmetic!(i64, "let a = AtomicCell::new(7i64);");
+            // An equal value is retried just like in `compare_exchange`, whether the failure was
+            // spurious or not.
+        }
     }
 }
 
-impl_arithmetic!(usize, atomic::AtomicUsize, "let a = AtomicCell::new(7usize);");
-impl_arithmetic!(isize, atomic::AtomicIsize, "let a = AtomicCell::new(7isize);");
-
-impl<T: Default> Default for AtomicCell<T> {
-    fn default() -> AtomicCell<T> {
-        AtomicCell::new(T::default())
-    }
+impl<T: Copy + ByteEq> AtomicCell<T> {
+    /// If the current value equals `current`, stores `new` into the atomic cell.
+    ///
+    /// Returns `true` if the value was updated, and `false` otherwise.
+    ///
+    /// This is [`compare_and_set`] for types whose equality is byte equality. See
+    /// [`compare_exchange_bytes`] for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    ///
+    /// let a = AtomicCell::new(7u64);
+    ///
+    /// assert_eq!(a.compare_and_set_bytes(1, 8), false);
+    /// assert_eq!(a.compare_and_set_bytes(7, 8), true);
+    /// assert_eq!(a.get(), 8);
+    /// ```
+    ///
+    /// [`compare_and_set`]: struct.AtomicCell.html#method.compare_and_set
+    /// [`compare_exchange_bytes`]: struct.AtomicCell.html#method.compare_exchange_bytes
+    pub fn compare_and_set_bytes(&self, current: T, new: T) -> bool {
+        self.compare_exchange_bytes(current, new).is_ok()
+    }
+
+    /// If the current value equals `current`, stores `new` into the atomic cell.
+    ///
+    /// The return value is a result indicating whether the new value was written and containing
+    /// the previous value, just like in [`compare_exchange`].
+    ///
+    /// Since `T` implements [`ByteEq`], values that are equal are also equal byte-for-byte, so a
+    /// single byte-wise compare-and-swap is always conclusive. Unlike [`compare_exchange`], this
+    /// method never has to retry after the bytes differ but the values compare equal.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    ///
+    /// let a = AtomicCell::new((1u32, 2u32));
+    ///
+    /// assert_eq!(a.compare_exchange_bytes((1, 3), (4, 5)), Err((1, 2)));
+    /// assert_eq!(a.compare_exchange_bytes((1, 2), (4, 5)), Ok((1, 2)));
+    /// assert_eq!(a.get(), (4, 5));
+    /// ```
+    ///
+    /// [`compare_exchange`]: struct.AtomicCell.html#method.compare_exchange
+    /// [`ByteEq`]: ../byte_eq/trait.ByteEq.html
+    pub fn compare_exchange_bytes(&self, current: T, new: T) -> Result<T, T> {
+        unsafe {
+            atomic_compare_exchange(
+                self.value.get(),
+                current,
+                new,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+        }
+    }
+}
+
+#[cfg(feature = "use_std")]
+impl<T: Copy + Eq> AtomicCell<T> {
+    /// Blocks the current thread while the value of the atomic cell equals `expected`.
+    ///
+    /// This function returns only once it has observed a different value. It is meant to be used
+    /// instead of spinning on [`get`] until another thread changes the value, which must be
+    /// followed by a call to [`notify_one`] or [`notify_all`] to wake up waiting threads. If the
+    /// value changes and changes back before this thread gets to observe it, it keeps waiting.
+    ///
+    /// On Linux, 4-byte types block using the futex syscall. Other types use a global table of
+    /// wait queues indexed by the address of the cell.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` is not lock-free, see [`is_lock_free`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate crossbeam;
+    /// # extern crate atomic;
+    /// use atomic::AtomicCell;
+    ///
+    /// # fn main() {
+    /// let a = AtomicCell::new(0u32);
+    ///
+    /// crossbeam::scope(|s| {
+    ///     s.spawn(|| {
+    ///         a.set(1);
+    ///         a.notify_all();
+    ///     });
+    ///
+    ///     a.wait(0);
+    ///     assert_eq!(a.get(), 1);
+    /// });
+    /// # }
+    /// ```
+    ///
+    /// [`get`]: struct.AtomicCell.html#method.get
+    /// [`notify_one`]: struct.AtomicCell.html#method.notify_one
+    /// [`notify_all`]: struct.AtomicCell.html#method.notify_all
+    /// [`is_lock_free`]: struct.AtomicCell.html#method.is_lock_free
+    pub fn wait(&self, expected: T) {
+        self.wait_until(expected, None);
+    }
+
+    /// Blocks the current thread while the value of the atomic cell equals `expected`, for at most
+    /// `dur`.
+    ///
+    /// Returns `true` if a different value was observed, and `false` if the timeout elapsed. See
+    /// [`wait`] for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` is not lock-free, see [`is_lock_free`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    /// use std::time::Duration;
+    ///
+    /// let a = AtomicCell::new(7u32);
+    ///
+    /// assert_eq!(a.wait_timeout(7, Duration::from_millis(10)), false);
+    /// assert_eq!(a.wait_timeout(8, Duration::from_millis(10)), true);
+    /// ```
+    ///
+    /// [`wait`]: struct.AtomicCell.html#method.wait
+    /// [`is_lock_free`]: struct.AtomicCell.html#method.is_lock_free
+    pub fn wait_timeout(&self, expected: T, dur: Duration) -> bool {
+        // A deadline too far in the future to represent is as good as none.
+        self.wait_until(expected, Instant::now().checked_add(dur))
+    }
+
+    /// Wakes up one thread blocked in [`wait`] or [`wait_timeout`] on this atomic cell, if there
+    /// is one.
+    ///
+    /// [`wait`]: struct.AtomicCell.html#method.wait
+    /// [`wait_timeout`]: struct.AtomicCell.html#method.wait_timeout
+    pub fn notify_one(&self) {
+        #[cfg(target_os = "linux")]
+        {
+            if uses_futex::<T>() {
+                unsafe { ::futex::wake(self.value.get() as *const u32, 1) };
+                return;
+            }
+        }
+
+        ::parking::unpark_one(self.value.get() as usize);
+    }
+
+    /// Wakes up all threads blocked in [`wait`] or [`wait_timeout`] on this atomic cell.
+    ///
+    /// [`wait`]: struct.AtomicCell.html#method.wait
+    /// [`wait_timeout`]: struct.AtomicCell.html#method.wait_timeout
+    pub fn notify_all(&self) {
+        #[cfg(target_os = "linux")]
+        {
+            if uses_futex::<T>() {
+                unsafe { ::futex::wake(self.value.get() as *const u32, i32::MAX) };
+                return;
+            }
+        }
+
+        ::parking::unpark_all(self.value.get() as usize);
+    }
+
+    fn wait_until(&self, expected: T, deadline: Option<Instant>) -> bool {
+        // Waiting on a value protected by a lock could park the thread while it holds a wait
+        // queue, and the lock table parks threads on the same queues.
+        assert!(
+            Self::is_lock_free(),
+            "waiting is only supported on lock-free atomic cells"
+        );
+
+        loop {
+            let mut raw = MaybeUninit::uninit();
+            let current = unsafe {
+                atomic_load_raw(self.value.get(), Ordering::SeqCst, &mut raw);
+                ptr::read(raw.as_ptr())
+            };
+            if current != expected {
+                return true;
+            }
+
+            let now = Instant::now();
+            if deadline.is_some_and(|d| now >= d) {
+                return false;
+            }
+
+            #[cfg(target_os = "linux")]
+            {
+                if uses_futex::<T>() {
+                    // The kernel compares bytes, so wait on the bytes just observed rather than
+                    // on `expected`, which may be equal without being identical.
+                    unsafe {
+                        ::futex::wait(
+                            self.value.get() as *const u32,
+                            ptr::read(raw.as_ptr() as *const u32),
+                            deadline.map(|d| d - now),
+                        );
+                    }
+                    continue;
+                }
+            }
+
+            ::parking::park(
+                self.value.get() as usize,
+                || self.get() == expected,
+                deadline,
+            );
+        }
+    }
+}
+
+macro_rules! impl_arithmetic {
+    ($t:ty, $example:tt) => {
+        impl AtomicCell<$t> {
+            /// Applies `f` to the inner value, stores the result, and returns the previous value.
+            ///
+            /// If the integer type can be transmuted into `AtomicUsize`, the operation is `native`
+            /// applied to the atomic, or a compare-and-swap loop on it if `native` is `None`.
+            /// Otherwise, it is a compare-and-swap loop on the cell, which uses a global lock
+            /// only if the cell is not lock-free.
+            #[inline]
+            fn fetch_op<F>(
+                &self,
+                order: Ordering,
+                native: Option<fn(&atomic::AtomicUsize, usize, Ordering) -> usize>,
+                val: $t,
+                f: F,
+            ) -> $t
+            where
+                F: Fn($t, $t) -> $t,
+            {
+                if can_transmute::<$t, atomic::AtomicUsize>() {
+                    let a = unsafe { &*(self.value.get() as *const atomic::AtomicUsize) };
+
+                    if let Some(native) = native {
+                        return native(a, val as usize, order) as $t;
+                    }
+
+                    let mut current = a.load(Ordering::Relaxed);
+                    loop {
+                        let new = f(current as $t, val) as usize;
+                        match a.compare_exchange_weak(
+                            current,
+                            new,
+                            order,
+                            failure_ordering(order),
+                        ) {
+                            Ok(previous) => return previous as $t,
+                            Err(previous) => current = previous,
+                        }
+                    }
+                } else {
+                    unsafe { atomic_fetch_op(self.value.get(), order, |x| f(x, val)) }
+                }
+            }
+
+            #[inline]
+            fn fetch_add_raw(&self, val: $t, order: Ordering) -> $t {
+                self.fetch_op(order, Some(atomic::AtomicUsize::fetch_add), val, |x, y| {
+                    x.wrapping_add(y)
+                })
+            }
+
+            #[inline]
+            fn fetch_sub_raw(&self, val: $t, order: Ordering) -> $t {
+                self.fetch_op(order, Some(atomic::AtomicUsize::fetch_sub), val, |x, y| {
+                    x.wrapping_sub(y)
+                })
+            }
+
+            #[inline]
+            fn fetch_and_raw(&self, val: $t, order: Ordering) -> $t {
+                self.fetch_op(order, Some(atomic::AtomicUsize::fetch_and), val, |x, y| x & y)
+            }
+
+            #[inline]
+            fn fetch_or_raw(&self, val: $t, order: Ordering) -> $t {
+                self.fetch_op(order, Some(atomic::AtomicUsize::fetch_or), val, |x, y| x | y)
+            }
+
+            #[inline]
+            fn fetch_xor_raw(&self, val: $t, order: Ordering) -> $t {
+                self.fetch_op(order, Some(atomic::AtomicUsize::fetch_xor), val, |x, y| x ^ y)
+            }
+
+            #[inline]
+            fn fetch_nand_raw(&self, val: $t, order: Ordering) -> $t {
+                self.fetch_op(order, Some(atomic::AtomicUsize::fetch_nand), val, |x, y| {
+                    !(x & y)
+                })
+            }
+
+            // `AtomicUsize::fetch_min` and `AtomicUsize::fetch_max` would compare signed types as
+            // unsigned, so those two always use a compare-and-swap loop.
+
+            #[inline]
+            fn fetch_min_raw(&self, val: $t, order: Ordering) -> $t {
+                self.fetch_op(order, None, val, |x, y| x.min(y))
+            }
+
+            #[inline]
+            fn fetch_max_raw(&self, val: $t, order: Ordering) -> $t {
+                self.fetch_op(order, None, val, |x, y| x.max(y))
+            }
+        }
+
+        impl_arithmetic!(@api $t, $example);
+    };
+    ($t:ty, $atomic:ty, $example:tt) => {
+        impl AtomicCell<$t> {
+            #[inline]
+            fn as_atomic(&self) -> &$atomic {
+                unsafe { &*(self.value.get() as *const $atomic) }
+            }
+
+            #[inline]
+            fn fetch_add_raw(&self, val: $t, order: Ordering) -> $t {
+                self.as_atomic().fetch_add(val, order)
+            }
+
+            #[inline]
+            fn fetch_sub_raw(&self, val: $t, order: Ordering) -> $t {
+                self.as_atomic().fetch_sub(val, order)
+            }
+
+            #[inline]
+            fn fetch_and_raw(&self, val: $t, order: Ordering) -> $t {
+                self.as_atomic().fetch_and(val, order)
+            }
+
+            #[inline]
+            fn fetch_or_raw(&self, val: $t, order: Ordering) -> $t {
+                self.as_atomic().fetch_or(val, order)
+            }
+
+            #[inline]
+            fn fetch_xor_raw(&self, val: $t, order: Ordering) -> $t {
+                self.as_atomic().fetch_xor(val, order)
+            }
+
+            #[inline]
+            fn fetch_nand_raw(&self, val: $t, order: Ordering) -> $t {
+                self.as_atomic().fetch_nand(val, order)
+            }
+
+            #[inline]
+            fn fetch_min_raw(&self, val: $t, order: Ordering) -> $t {
+                self.as_atomic().fetch_min(val, order)
+            }
+
+            #[inline]
+            fn fetch_max_raw(&self, val: $t, order: Ordering) -> $t {
+                self.as_atomic().fetch_max(val, order)
+            }
+        }
+
+        impl_arithmetic!(@api $t, $example);
+    };
+    (@api $t:ty, $example:tt) => {
+        impl AtomicCell<$t> {
+            /// Increments the inner value by `val` and returns the new value.
+            ///
+            /// The addition wraps on overflow. Note that `atomic_cell.add(val)` is equivalent to:
+            ///
+            /// ```ignore
+            /// atomic_cell.update(|x| x.wrapping_add(val))
+            /// ```
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.add(3), 10);
+            /// assert_eq!(a.get(), 10);
+            /// ```
+            #[inline]
+            pub fn add(&self, val: $t) -> $t {
+                self.add_with(val, Ordering::SeqCst)
+            }
+
+            /// Increments the inner value by `val` using the given memory ordering and returns the
+            /// new value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            /// use std::sync::atomic::Ordering::Relaxed;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.add_with(3, Relaxed), 10);
+            /// assert_eq!(a.get(), 10);
+            /// ```
+            #[inline]
+            pub fn add_with(&self, val: $t, order: Ordering) -> $t {
+                self.fetch_add_raw(val, order).wrapping_add(val)
+            }
+
+            /// Decrements the inner value by `val` and returns the new value.
+            ///
+            /// The subtraction wraps on overflow. Note that `atomic_cell.sub(val)` is equivalent
+            /// to:
+            ///
+            /// ```ignore
+            /// atomic_cell.update(|x| x.wrapping_sub(val))
+            /// ```
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.sub(3), 4);
+            /// assert_eq!(a.get(), 4);
+            /// ```
+            #[inline]
+            pub fn sub(&self, val: $t) -> $t {
+                self.sub_with(val, Ordering::SeqCst)
+            }
+
+            /// Decrements the inner value by `val` using the given memory ordering and returns the
+            /// new value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            /// use std::sync::atomic::Ordering::Relaxed;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.sub_with(3, Relaxed), 4);
+            /// assert_eq!(a.get(), 4);
+            /// ```
+            #[inline]
+            pub fn sub_with(&self, val: $t, order: Ordering) -> $t {
+                self.fetch_sub_raw(val, order).wrapping_sub(val)
+            }
+
+            /// Increments the inner value by `val` and returns the previous value.
+            ///
+            /// The addition wraps on overflow. This is the equivalent of `fetch_add` on the
+            /// primitive atomic types, while [`add`] returns the new value instead.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.fetch_add(3), 7);
+            /// assert_eq!(a.get(), 10);
+            /// ```
+            ///
+            /// [`add`]: struct.AtomicCell.html#method.add
+            #[inline]
+            pub fn fetch_add(&self, val: $t) -> $t {
+                self.fetch_add_raw(val, Ordering::SeqCst)
+            }
+
+            /// Decrements the inner value by `val` and returns the previous value.
+            ///
+            /// The subtraction wraps on overflow. This is the equivalent of `fetch_sub` on the
+            /// primitive atomic types, while [`sub`] returns the new value instead.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.fetch_sub(3), 7);
+            /// assert_eq!(a.get(), 4);
+            /// ```
+            ///
+            /// [`sub`]: struct.AtomicCell.html#method.sub
+            #[inline]
+            pub fn fetch_sub(&self, val: $t) -> $t {
+                self.fetch_sub_raw(val, Ordering::SeqCst)
+            }
+
+            /// Increments the inner value by `val` unless that would overflow.
+            ///
+            /// Returns the new value, or `None` if the addition would overflow, in which case the
+            /// inner value is left untouched.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.checked_add(3), Some(10));
+            ///
+            #[doc = concat!("a.set(", stringify!($t), "::MAX);")]
+            /// assert_eq!(a.checked_add(1), None);
+            #[doc = concat!("assert_eq!(a.get(), ", stringify!($t), "::MAX);")]
+            /// ```
+            #[inline]
+            pub fn checked_add(&self, val: $t) -> Option<$t> {
+                self.fetch_update(|x| x.checked_add(val))
+                    .ok()
+                    .map(|x| x.wrapping_add(val))
+            }
+
+            /// Decrements the inner value by `val` unless that would overflow.
+            ///
+            /// Returns the new value, or `None` if the subtraction would overflow, in which case
+            /// the inner value is left untouched.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.checked_sub(3), Some(4));
+            ///
+            #[doc = concat!("a.set(", stringify!($t), "::MIN);")]
+            /// assert_eq!(a.checked_sub(1), None);
+            #[doc = concat!("assert_eq!(a.get(), ", stringify!($t), "::MIN);")]
+            /// ```
+            #[inline]
+            pub fn checked_sub(&self, val: $t) -> Option<$t> {
+                self.fetch_update(|x| x.checked_sub(val))
+                    .ok()
+                    .map(|x| x.wrapping_sub(val))
+            }
+
+            /// Increments the inner value by `val`, saturating at the numeric bound instead of
+            /// overflowing, and returns the new value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.saturating_add(3), 10);
+            ///
+            #[doc = concat!("a.set(", stringify!($t), "::MAX);")]
+            #[doc = concat!("assert_eq!(a.saturating_add(1), ", stringify!($t), "::MAX);")]
+            /// ```
+            #[inline]
+            pub fn saturating_add(&self, val: $t) -> $t {
+                self.update(|x| x.saturating_add(val))
+            }
+
+            /// Decrements the inner value by `val`, saturating at the numeric bound instead of
+            /// overflowing, and returns the new value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.saturating_sub(3), 4);
+            ///
+            #[doc = concat!("a.set(", stringify!($t), "::MIN);")]
+            #[doc = concat!("assert_eq!(a.saturating_sub(1), ", stringify!($t), "::MIN);")]
+            /// ```
+            #[inline]
+            pub fn saturating_sub(&self, val: $t) -> $t {
+                self.update(|x| x.saturating_sub(val))
+            }
+
+            /// Applies bitwise "and" to the inner value and `val`, and returns the new value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.and(3), 3);
+            /// assert_eq!(a.get(), 3);
+            /// ```
+            #[inline]
+            pub fn and(&self, val: $t) -> $t {
+                self.fetch_and(val) & val
+            }
+
+            /// Applies bitwise "and" to the inner value and `val`, and returns the previous value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.fetch_and(3), 7);
+            /// assert_eq!(a.get(), 3);
+            /// ```
+            #[inline]
+            pub fn fetch_and(&self, val: $t) -> $t {
+                self.fetch_and_raw(val, Ordering::SeqCst)
+            }
+
+            /// Applies bitwise "or" to the inner value and `val`, and returns the new value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.or(8), 15);
+            /// assert_eq!(a.get(), 15);
+            /// ```
+            #[inline]
+            pub fn or(&self, val: $t) -> $t {
+                self.fetch_or(val) | val
+            }
+
+            /// Applies bitwise "or" to the inner value and `val`, and returns the previous value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.fetch_or(8), 7);
+            /// assert_eq!(a.get(), 15);
+            /// ```
+            #[inline]
+            pub fn fetch_or(&self, val: $t) -> $t {
+                self.fetch_or_raw(val, Ordering::SeqCst)
+            }
+
+            /// Applies bitwise "xor" to the inner value and `val`, and returns the new value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.xor(2), 5);
+            /// assert_eq!(a.get(), 5);
+            /// ```
+            #[inline]
+            pub fn xor(&self, val: $t) -> $t {
+                self.fetch_xor(val) ^ val
+            }
+
+            /// Applies bitwise "xor" to the inner value and `val`, and returns the previous value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.fetch_xor(2), 7);
+            /// assert_eq!(a.get(), 5);
+            /// ```
+            #[inline]
+            pub fn fetch_xor(&self, val: $t) -> $t {
+                self.fetch_xor_raw(val, Ordering::SeqCst)
+            }
+
+            /// Applies bitwise "nand" to the inner value and `val`, and returns the new value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.nand(3), !3);
+            /// assert_eq!(a.get(), !3);
+            /// ```
+            #[inline]
+            pub fn nand(&self, val: $t) -> $t {
+                !(self.fetch_nand(val) & val)
+            }
+
+            /// Applies bitwise "nand" to the inner value and `val`, and returns the previous value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.fetch_nand(3), 7);
+            /// assert_eq!(a.get(), !3);
+            /// ```
+            #[inline]
+            pub fn fetch_nand(&self, val: $t) -> $t {
+                self.fetch_nand_raw(val, Ordering::SeqCst)
+            }
+
+            /// Sets the inner value to the minimum of itself and `val`, and returns the new value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.min(9), 7);
+            /// assert_eq!(a.min(3), 3);
+            /// assert_eq!(a.get(), 3);
+            /// ```
+            #[inline]
+            pub fn min(&self, val: $t) -> $t {
+                self.fetch_min(val).min(val)
+            }
+
+            /// Sets the inner value to the minimum of itself and `val`, and returns the previous
+            /// value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.fetch_min(3), 7);
+            /// assert_eq!(a.get(), 3);
+            /// ```
+            #[inline]
+            pub fn fetch_min(&self, val: $t) -> $t {
+                self.fetch_min_raw(val, Ordering::SeqCst)
+            }
+
+            /// Sets the inner value to the maximum of itself and `val`, and returns the new value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.max(3), 7);
+            /// assert_eq!(a.max(9), 9);
+            /// assert_eq!(a.get(), 9);
+            /// ```
+            #[inline]
+            pub fn max(&self, val: $t) -> $t {
+                self.fetch_max(val).max(val)
+            }
+
+            /// Sets the inner value to the maximum of itself and `val`, and returns the previous
+            /// value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            ///
+            #[doc = $example]
+            ///
+            /// assert_eq!(a.fetch_max(9), 7);
+            /// assert_eq!(a.get(), 9);
+            /// ```
+            #[inline]
+            pub fn fetch_max(&self, val: $t) -> $t {
+                self.fetch_max_raw(val, Ordering::SeqCst)
+            }
+        }
+    };
+}
+
+#[cfg(target_has_atomic = "8")]
+impl_arithmetic!(u8, atomic::AtomicU8, "let a = AtomicCell::new(7u8);");
+#[cfg(not(target_has_atomic = "8"))]
+impl_arithmetic!(u8, "let a = AtomicCell::new(7u8);");
+#[cfg(target_has_atomic = "8")]
+impl_arithmetic!(i8, atomic::AtomicI8, "let a = AtomicCell::new(7i8);");
+#[cfg(not(target_has_atomic = "8"))]
+impl_arithmetic!(i8, "let a = AtomicCell::new(7i8);");
+#[cfg(target_has_atomic = "16")]
+impl_arithmetic!(u16, atomic::AtomicU16, "let a = AtomicCell::new(7u16);");
+#[cfg(not(target_has_atomic = "16"))]
+impl_arithmetic!(u16, "let a = AtomicCell::new(7u16);");
+#[cfg(target_has_atomic = "16")]
+impl_arithmetic!(i16, atomic::AtomicI16, "let a = AtomicCell::new(7i16);");
+#[cfg(not(target_has_atomic = "16"))]
+impl_arithmetic!(i16, "let a = AtomicCell::new(7i16);");
+#[cfg(target_has_atomic = "32")]
+impl_arithmetic!(u32, atomic::AtomicU32, "let a = AtomicCell::new(7u32);");
+#[cfg(not(target_has_atomic = "32"))]
+impl_arithmetic!(u32, "let a = AtomicCell::new(7u32);");
+#[cfg(target_has_atomic = "32")]
+impl_arithmetic!(i32, atomic::AtomicI32, "let a = AtomicCell::new(7i32);");
+#[cfg(not(target_has_atomic = "32"))]
+impl_arithmetic!(i32, "let a = AtomicCell::new(7i32);");
+#[cfg(target_has_atomic = "64")]
+impl_arithmetic!(u64, atomic::AtomicU64, "let a = AtomicCell::new(7u64);");
+#[cfg(not(target_has_atomic = "64"))]
+impl_arithmetic!(u64, "let a = AtomicCell::new(7u64);");
+#[cfg(target_has_atomic = "64")]
+impl_arithmetic!(i64, atomic::AtomicI64, "let a = AtomicCell::new(7i64);");
+#[cfg(not(target_has_atomic = "64"))]
+impl_arithmetic!(i64, "let a = AtomicCell::new(7i64);");
+impl_arithmetic!(usize, atomic::AtomicUsize, "let a = AtomicCell::new(7usize);");
+impl_arithmetic!(isize, atomic::AtomicIsize, "let a = AtomicCell::new(7isize);");
+
+/// Logical operations on `AtomicCell<bool>`.
+///
+/// Every operation stores a valid `bool`, so the byte inside the cell is always either 0 or 1.
+/// This also holds for the lock-based fallback, which means that the address returned by
+/// [`AtomicCell::as_ptr`] can always be read as a `bool`.
+///
+/// [`AtomicCell::as_ptr`]: struct.AtomicCell.html#method.as_ptr
+impl AtomicCell<bool> {
+    /// Applies logical "and" to the inner value and `val`, and returns the previous value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    ///
+    /// let a = AtomicCell::new(true);
+    ///
+    /// assert_eq!(a.fetch_and(false), true);
+    /// assert_eq!(a.get(), false);
+    /// ```
+    #[inline]
+    pub fn fetch_and(&self, val: bool) -> bool {
+        match self.as_atomic_bool() {
+            Some(a) => a.fetch_and(val, Ordering::SeqCst),
+            None => self.fetch_op(|x| x & val),
+        }
+    }
+
+    /// Applies logical "or" to the inner value and `val`, and returns the previous value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    ///
+    /// let a = AtomicCell::new(false);
+    ///
+    /// assert_eq!(a.fetch_or(true), false);
+    /// assert_eq!(a.get(), true);
+    /// ```
+    #[inline]
+    pub fn fetch_or(&self, val: bool) -> bool {
+        match self.as_atomic_bool() {
+            Some(a) => a.fetch_or(val, Ordering::SeqCst),
+            None => self.fetch_op(|x| x | val),
+        }
+    }
+
+    /// Applies logical "xor" to the inner value and `val`, and returns the previous value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    ///
+    /// let a = AtomicCell::new(true);
+    ///
+    /// assert_eq!(a.fetch_xor(true), true);
+    /// assert_eq!(a.get(), false);
+    /// ```
+    #[inline]
+    pub fn fetch_xor(&self, val: bool) -> bool {
+        match self.as_atomic_bool() {
+            Some(a) => a.fetch_xor(val, Ordering::SeqCst),
+            None => self.fetch_op(|x| x ^ val),
+        }
+    }
+
+    /// Applies logical "not" to the inner value, and returns the previous value.
+    ///
+    /// Note that `atomic_cell.fetch_not()` is equivalent to:
+    ///
+    /// ```ignore
+    /// atomic_cell.fetch_xor(true)
+    /// ```
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    ///
+    /// let a = AtomicCell::new(true);
+    ///
+    /// assert_eq!(a.fetch_not(), true);
+    /// assert_eq!(a.fetch_not(), false);
+    /// assert_eq!(a.get(), true);
+    /// ```
+    #[inline]
+    pub fn fetch_not(&self) -> bool {
+        self.fetch_xor(true)
+    }
+
+    /// Applies logical "nand" to the inner value and `val`, and returns the previous value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    ///
+    /// let a = AtomicCell::new(true);
+    ///
+    /// assert_eq!(a.fetch_nand(true), true);
+    /// assert_eq!(a.get(), false);
+    /// assert_eq!(a.fetch_nand(true), false);
+    /// assert_eq!(a.get(), true);
+    /// ```
+    #[inline]
+    pub fn fetch_nand(&self, val: bool) -> bool {
+        match self.as_atomic_bool() {
+            Some(a) => a.fetch_nand(val, Ordering::SeqCst),
+            None => self.fetch_op(|x| !(x & val)),
+        }
+    }
+
+    /// Returns the inner value as an `AtomicBool` if operations on it can use atomic instructions.
+    ///
+    /// Mixing atomic instructions with accesses protected by a global lock would not be atomic, so
+    /// this is only possible if every other operation on the cell uses `AtomicBool`-sized
+    /// instructions, too.
+    #[inline]
+    fn as_atomic_bool(&self) -> Option<&AtomicBool> {
+        if can_transmute::<bool, AtomicBool>() && atomic_is_native::<bool>() {
+            Some(unsafe { &*(self.value.get() as *const AtomicBool) })
+        } else {
+            None
+        }
+    }
+
+    /// Applies `f` to the inner value, and returns the previous value.
+    #[inline]
+    fn fetch_op<F>(&self, f: F) -> bool
+    where
+        F: FnMut(bool) -> bool,
+    {
+        unsafe { atomic_fetch_op(self.value.get(), Ordering::SeqCst, f) }
+    }
+}
+
+#[cfg(feature = "use_std")]
+macro_rules! impl_option_pointer {
+    ($p:ident, $import:tt) => {
+        #[doc = concat!("Operations on `AtomicCell<Option<", stringify!($p), "<T>>>`.")]
+        ///
+        /// `None` is represented by the null pointer, so the cell is pointer-sized and lock-free,
+        /// and each of these operations is a single atomic instruction. The value can be taken out
+        /// with [`take`].
+        ///
+        /// [`take`]: struct.AtomicCell.html#method.take
+        impl<T> AtomicCell<Option<$p<T>>> {
+            // The null pointer niche gives `Option<Box<T>>` and `Option<Arc<T>>` for sized `T`
+            // the layout of a pointer, with zero for `None`. The stored pointer is not necessarily
+            // a pointer to `T` (an `Arc` points to its reference counts), so it is only ever
+            // converted back into the smart pointer.
+
+            #[inline]
+            fn as_atomic_usize(&self) -> &atomic::AtomicUsize {
+                unsafe { &*(self.value.get() as *const atomic::AtomicUsize) }
+            }
+
+            #[inline]
+            fn into_raw(val: $p<T>) -> usize {
+                let raw = unsafe { mem::transmute_copy(&val) };
+                mem::forget(val);
+                raw
+            }
+
+            #[inline]
+            unsafe fn from_raw(raw: usize) -> $p<T> {
+                mem::transmute_copy(&raw)
+            }
+
+            /// Returns a reference to the value owned by the smart pointer represented by `raw`.
+            #[inline]
+            unsafe fn deref_raw<'a>(raw: usize) -> &'a T {
+                let p = &*(&raw as *const usize as *const $p<T>);
+                &*(&**p as *const T)
+            }
+
+            /// Stores `val` into the atomic cell if it is empty.
+            ///
+            /// If the atomic cell already holds a value, `val` is given back.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            #[doc = $import]
+            ///
+            #[doc = concat!("let a = AtomicCell::<Option<", stringify!($p), "<i32>>>::new(None);")]
+            ///
+            #[doc = concat!("assert!(a.try_set(", stringify!($p), "::new(7)).is_ok());")]
+            #[doc = concat!("assert_eq!(a.try_set(", stringify!($p), "::new(8)), Err(", stringify!($p), "::new(8)));")]
+            #[doc = concat!("assert_eq!(a.take(), Some(", stringify!($p), "::new(7)));")]
+            /// ```
+            pub fn try_set(&self, val: $p<T>) -> Result<(), $p<T>> {
+                let new = Self::into_raw(val);
+
+                match self.as_atomic_usize().compare_exchange(
+                    0,
+                    new,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                ) {
+                    Ok(_) => Ok(()),
+                    Err(_) => unsafe { Err(Self::from_raw(new)) },
+                }
+            }
+
+            /// Returns `true` if the atomic cell holds a value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            #[doc = $import]
+            ///
+            #[doc = concat!("let a = AtomicCell::new(Some(", stringify!($p), "::new(7)));")]
+            ///
+            /// assert!(a.is_some());
+            /// a.take();
+            /// assert!(!a.is_some());
+            /// ```
+            pub fn is_some(&self) -> bool {
+                self.as_atomic_usize().load(Ordering::SeqCst) != 0
+            }
+
+            /// Returns a reference to the value in the atomic cell, initializing it with `f` first
+            /// if the atomic cell is empty.
+            ///
+            /// Several threads may call `f` concurrently, but only one of the results is stored,
+            /// and the others are dropped.
+            ///
+            /// # Safety
+            ///
+            /// The returned reference points into the stored value, which is not protected from
+            /// being dropped. The caller must make sure that the value is not taken out of or
+            /// replaced in the atomic cell while the reference is alive.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use atomic::AtomicCell;
+            #[doc = $import]
+            ///
+            #[doc = concat!("let a = AtomicCell::<Option<", stringify!($p), "<i32>>>::new(None);")]
+            ///
+            #[doc = concat!("assert_eq!(unsafe { a.get_or_init(|| ", stringify!($p), "::new(7)) }, &7);")]
+            #[doc = concat!("assert_eq!(unsafe { a.get_or_init(|| ", stringify!($p), "::new(8)) }, &7);")]
+            /// ```
+            pub unsafe fn get_or_init<F>(&self, f: F) -> &T
+            where
+                F: FnOnce() -> $p<T>,
+            {
+                let a = self.as_atomic_usize();
+
+                let current = a.load(Ordering::SeqCst);
+                if current != 0 {
+                    return Self::deref_raw(current);
+                }
+
+                let new = Self::into_raw(f());
+                match a.compare_exchange(0, new, Ordering::SeqCst, Ordering::SeqCst) {
+                    Ok(_) => Self::deref_raw(new),
+                    Err(current) => {
+                        drop(Self::from_raw(new));
+                        Self::deref_raw(current)
+                    }
+                }
+            }
+        }
+    };
+}
+#[cfg(feature = "use_std")]
+impl_option_pointer!(Box, "");
+#[cfg(feature = "use_std")]
+impl_option_pointer!(Arc, "use std::sync::Arc;");
+
+/// Pointer arithmetic and tagging on `AtomicCell<*mut T>`.
+///
+/// A pointer to `T` is always a multiple of `align_of::<T>()`, so its low bits are zero and can
+/// carry a tag instead, for example to mark deleted nodes in a lock-free list. A tag is any number
+/// less than `align_of::<T>()`. Pointer arithmetic leaves the tag intact.
+///
+/// Raw pointers are word-sized, so these are single atomic instructions on `AtomicUsize`.
+///
+/// Raw pointers are not `Send`, so unlike `AtomicPtr<T>`, `AtomicCell<*mut T>` is not `Sync`. To
+/// share it between threads, wrap it in a type that asserts it is safe to do so.
+impl<T> AtomicCell<*mut T> {
+    #[inline]
+    fn as_atomic_usize(&self) -> &atomic::AtomicUsize {
+        unsafe { &*(self.value.get() as *const atomic::AtomicUsize) }
+    }
+
+    /// Returns the mask of bits that can hold a tag.
+    #[inline]
+    fn tag_mask() -> usize {
+        mem::align_of::<T>() - 1
+    }
+
+    /// Offsets the pointer by `count` elements of `T` and returns the previous pointer.
+    ///
+    /// The offset wraps around the address space, like [`wrapping_add`] on pointers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    ///
+    /// let mut v = [1, 2, 3];
+    /// let a = AtomicCell::new(v.as_mut_ptr());
+    ///
+    /// assert_eq!(a.fetch_ptr_add(2), v.as_mut_ptr());
+    /// assert_eq!(unsafe { *a.get() }, 3);
+    /// ```
+    ///
+    /// [`wrapping_add`]: https://doc.rust-lang.org/std/primitive.pointer.html#method.wrapping_add
+    pub fn fetch_ptr_add(&self, count: usize) -> *mut T {
+        let bytes = count.wrapping_mul(mem::size_of::<T>());
+        self.as_atomic_usize().fetch_add(bytes, Ordering::SeqCst) as *mut T
+    }
+
+    /// Offsets the pointer by `count` elements of `T` backwards and returns the previous pointer.
+    ///
+    /// The offset wraps around the address space, like [`wrapping_sub`] on pointers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    ///
+    /// let mut v = [1, 2, 3];
+    /// let a = AtomicCell::new(v[2..].as_mut_ptr());
+    ///
+    /// assert_eq!(a.fetch_ptr_sub(2), v[2..].as_mut_ptr());
+    /// assert_eq!(unsafe { *a.get() }, 1);
+    /// ```
+    ///
+    /// [`wrapping_sub`]: https://doc.rust-lang.org/std/primitive.pointer.html#method.wrapping_sub
+    pub fn fetch_ptr_sub(&self, count: usize) -> *mut T {
+        let bytes = count.wrapping_mul(mem::size_of::<T>());
+        self.as_atomic_usize().fetch_sub(bytes, Ordering::SeqCst) as *mut T
+    }
+
+    /// Sets the bits of `tag` in the tag of the pointer, and returns the previous pointer.
+    ///
+    /// Bits of `tag` that don't fit below the alignment of `T` are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    ///
+    /// let mut x = 7u64;
+    /// let p = &mut x as *mut u64;
+    /// let a = AtomicCell::new(p);
+    ///
+    /// assert_eq!(a.fetch_or_tag(1), p);
+    /// assert_eq!(a.get() as usize, p as usize | 1);
+    /// ```
+    pub fn fetch_or_tag(&self, tag: usize) -> *mut T {
+        self.as_atomic_usize()
+            .fetch_or(tag & Self::tag_mask(), Ordering::SeqCst) as *mut T
+    }
+
+    /// Clears the bits of the tag of the pointer that are not set in `tag`, and returns the
+    /// previous pointer.
+    ///
+    /// The address is left intact regardless of `tag`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    ///
+    /// let mut x = 7u64;
+    /// let p = &mut x as *mut u64;
+    /// let a = AtomicCell::new((p as usize | 3) as *mut u64);
+    ///
+    /// assert_eq!(a.fetch_and_tag(1) as usize, p as usize | 3);
+    /// assert_eq!(a.get() as usize, p as usize | 1);
+    /// ```
+    pub fn fetch_and_tag(&self, tag: usize) -> *mut T {
+        self.as_atomic_usize()
+            .fetch_and(tag | !Self::tag_mask(), Ordering::SeqCst) as *mut T
+    }
+
+    /// Replaces the tag of the pointer with `tag`, and returns the previous pointer.
+    ///
+    /// Bits of `tag` that don't fit below the alignment of `T` are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::AtomicCell;
+    ///
+    /// let mut x = 7u64;
+    /// let p = &mut x as *mut u64;
+    /// let a = AtomicCell::new((p as usize | 1) as *mut u64);
+    ///
+    /// assert_eq!(a.with_tag(2) as usize, p as usize | 1);
+    /// assert_eq!(a.get() as usize, p as usize | 2);
+    /// assert_eq!(a.with_tag(0) as usize, p as usize | 2);
+    /// assert_eq!(a.get(), p);
+    /// ```
+    pub fn with_tag(&self, tag: usize) -> *mut T {
+        let mask = Self::tag_mask();
+        let tag = tag & mask;
+
+        let a = self.as_atomic_usize();
+        let mut current = a.load(Ordering::Relaxed);
+        loop {
+            let new = (current & !mask) | tag;
+            match a.compare_exchange_weak(current, new, Ordering::SeqCst, Ordering::Relaxed) {
+                Ok(previous) => return previous as *mut T,
+                Err(previous) => current = previous,
+            }
+        }
+    }
+}
+
+impl<T: Default> Default for AtomicCell<T> {
+    fn default() -> AtomicCell<T> {
+        AtomicCell::new(T::default())
+    }
 }
 
 impl<T: Copy + fmt::Debug> fmt::Debug for AtomicCell<T> {
@@ -460,6 +1865,28 @@ impl<T: Copy + fmt::Debug> fmt::Debug for AtomicCell<T> {
     }
 }
 
+/// A value aligned to 16 bytes.
+///
+/// Values of 16 bytes are only lock-free in an `AtomicCell` if they are also aligned to 16 bytes,
+/// which most of them are not. For example, `(usize, usize)` is aligned to 8 bytes on 64-bit
+/// targets, so `AtomicCell<(usize, usize)>` uses locks, while `AtomicCell<Align16<(usize,
+/// usize)>>` can use a 16-byte compare-and-swap.
+///
+/// # Examples
+///
+/// ```
+/// use atomic::AtomicCell;
+/// use atomic::atomic_cell::Align16;
+///
+/// let a = AtomicCell::new(Align16((1usize, 2usize)));
+///
+/// assert_eq!(a.compare_exchange(Align16((1, 2)), Align16((3, 4))), Ok(Align16((1, 2))));
+/// assert_eq!(a.get().0, (3, 4));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[repr(C, align(16))]
+pub struct Align16<T>(pub T);
+
 /// Returns `true` if the two values are equal byte-for-byte.
 fn byte_eq<T>(a: &T, b: &T) -> bool {
     unsafe {
@@ -470,79 +1897,323 @@ fn byte_eq<T>(a: &T, b: &T) -> bool {
 }
 
 /// Returns `true` if values of type `A` can be transmuted into values of type `B`.
-fn can_transmute<A, B>() -> bool {
+pub(crate) fn can_transmute<A, B>() -> bool {
     // Sizes must be equal, but alignment of `A` must be greater or equal than that of `B`.
     mem::size_of::<A>() == mem::size_of::<B>() && mem::align_of::<A>() >= mem::align_of::<B>()
 }
 
+// Defines `LOCK_STRIPES`, which can be set through the `ATOMIC_CELL_LOCK_STRIPES` environment
+// variable at build time.
+include!(concat!(env!("OUT_DIR"), "/lock_stripes.rs"));
+
+/// The state of a lock that is currently held.
+///
+/// Every unlocked state is an even stamp that is incremented each time the lock is released, so
+/// readers can tell whether the protected data was modified while they were reading it.
+const LOCKED: usize = 1;
+
+/// The state of a lock that is currently held while other threads are parked waiting for it.
+#[cfg(feature = "parking")]
+const LOCKED_PARKED: usize = 3;
+
+/// Returns `true` if the lock state is one of the held states.
+#[inline]
+fn is_locked(state: usize) -> bool {
+    state & 1 != 0
+}
+
+/// The global lock table.
+///
+/// Each lock is a sequence lock: writers hold it exclusively, while readers only check that its
+/// stamp didn't change while they were reading, see [`read_optimistic`].
+///
+/// [`read_optimistic`]: fn.read_optimistic.html
+static LOCKS: [atomic::AtomicUsize; LOCK_STRIPES] =
+    [const { atomic::AtomicUsize::new(0) }; LOCK_STRIPES];
+
+/// The threads holding the locks in `LOCKS`, or zero.
+#[cfg(all(debug_assertions, feature = "use_std"))]
+static OWNERS: [atomic::AtomicUsize; LOCK_STRIPES] =
+    [const { atomic::AtomicUsize::new(0) }; LOCK_STRIPES];
+
 /// Automatically releases a lock when dropped.
 struct LockGuard {
-    lock: &'static AtomicBool,
+    lock: &'static atomic::AtomicUsize,
+
+    /// The stamp of the lock before it was acquired.
+    stamp: usize,
+
+    /// Whether the protected operation is sequentially consistent.
+    seq_cst: bool,
+
+    /// The owner of the lock, cleared on release.
+    #[cfg(all(debug_assertions, feature = "use_std"))]
+    owner: &'static atomic::AtomicUsize,
 }
 
 impl Drop for LockGuard {
     #[inline]
     fn drop(&mut self) {
-        self.lock.store(false, Ordering::Release);
+        if self.seq_cst {
+            atomic::fence(Ordering::SeqCst);
+        }
+
+        #[cfg(all(debug_assertions, feature = "use_std"))]
+        self.owner.store(0, Ordering::Relaxed);
+
+        // Skipping `LOCKED` keeps the stamp even.
+        #[cfg(not(feature = "parking"))]
+        self.lock.store(self.stamp.wrapping_add(2), Ordering::Release);
+
+        #[cfg(feature = "parking")]
+        {
+            if self.lock.swap(self.stamp.wrapping_add(2), Ordering::Release) == LOCKED_PARKED {
+                ::parking::unpark_all(self.lock as *const _ as usize);
+            }
+        }
     }
 }
 
+/// Returns a number identifying the current thread, which is never zero.
+///
+/// The number is the address of a thread-local variable, so a thread started after another one
+/// has exited may get the same number. That doesn't confuse the re-locking check in [`lock`]:
+/// every lock is released before the thread holding it can exit, which clears its entry in
+/// `OWNERS`. Only a leaked `LockGuard` could leave a stale entry behind, and then the lock stays
+/// held forever anyway.
+///
+/// [`lock`]: fn.lock.html
+#[cfg(all(debug_assertions, feature = "use_std"))]
+fn current_thread_id() -> usize {
+    thread_local!(static ID: u8 = const { 0 });
+    ID.with(|id| id as *const u8 as usize)
+}
+
+/// Returns the index of the lock for atomic data stored at the given address.
+#[inline]
+fn lock_index(addr: usize) -> usize {
+    // If the modulus is a constant number, the compiler will use crazy math to transform this into
+    // a sequence of cheap arithmetic operations rather than using the slow modulo instruction.
+    addr % LOCK_STRIPES
+}
+
+/// Returns `true` if waiting on `AtomicCell<T>` uses the futex syscall, which works on any aligned
+/// 4-byte value.
+#[cfg(all(feature = "use_std", target_os = "linux"))]
+fn uses_futex<T>() -> bool {
+    mem::size_of::<T>() == 4 && mem::align_of::<T>() >= 4
+}
+
 /// Acquires the lock for atomic data stored at the given address.
 ///
 /// This function is used to protect atomic data which doesn't fit into any of the primitive atomic
 /// types in `std::sync::atomic`. Operations on such atomics must therefore use a global lock.
 ///
-/// However, there is not only one global lock but an array of many locks, and one of them is
-/// picked based on the given address. Having many locks reduces contention and improves
+/// However, there is not only one global lock but an array of `LOCK_STRIPES` locks, and one of
+/// them is picked based on the given address. Having many locks reduces contention and improves
 /// scalability.
+///
+/// Acquiring and releasing the lock already makes the protected operation at least as strong as
+/// `Acquire`, `Release`, or `AcqRel`. If `order` is `SeqCst`, the operation is additionally
+/// surrounded by `SeqCst` fences so that it takes part in the single total order of all
+/// sequentially consistent operations.
+///
+/// # Panics
+///
+/// In debug builds with the `use_std` feature, panics if the current thread is already holding the
+/// lock, since waiting for it would never finish. Two different addresses can share a lock, so
+/// nested operations on unrelated atomics would deadlock. The public API never runs user code,
+/// such as closures or `Drop` impls, while holding a lock, so this can only be caused by a bug in
+/// this module.
 #[inline]
-fn lock(addr: usize) -> LockGuard {
-    // The number of locks is prime.
-    const LEN: usize = 499;
-
-    const A: AtomicBool = AtomicBool::new(false);
-    static LOCKS: [AtomicBool; LEN] = [
-        A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A,
-        A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A,
-        A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A,
-        A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A,
-        A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A,
-        A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A,
-        A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A,
-        A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A,
-        A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A,
-        A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A,
-        A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A,
-        A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A,
-        A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A,
-        A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A,
-        A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A,
-        A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A,
-        A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A, A,
-    ];
+fn lock(addr: usize, order: Ordering) -> LockGuard {
+    let index = lock_index(addr);
+    let lock = &LOCKS[index];
 
-    // If the modulus is a constant number, the compiler will use crazy math to transform this into
-    // a sequence of cheap arithmetic operations rather than using the slow modulo instruction.
-    let lock = &LOCKS[addr % LEN];
+    #[cfg(all(debug_assertions, feature = "use_std"))]
+    let (owner, me) = {
+        // Only the current thread ever stores its own id, so a relaxed load sees it if and only if
+        // the current thread holds the lock.
+        let owner = &OWNERS[index];
+        let me = current_thread_id();
+        if owner.load(Ordering::Relaxed) == me {
+            panic!(
+                "AtomicCell lock #{} (for address {:#x}) is already held by the current thread; \
+                 nested operations on atomic cells sharing a lock would deadlock",
+                index, addr
+            );
+        }
+        (owner, me)
+    };
 
     let mut step = 0usize;
 
-    while lock.compare_and_swap(false, true, Ordering::Acquire) {
+    let stamp = loop {
+        let stamp = lock.load(Ordering::Relaxed);
+        if !is_locked(stamp)
+            && lock
+                .compare_exchange_weak(stamp, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        {
+            break stamp;
+        }
+
         if step < 5 {
             // Just try again.
         } else if step < 10 {
-            atomic::spin_loop_hint();
+            hint::spin_loop();
         } else {
             #[cfg(not(feature = "use_std"))]
-            atomic::spin_loop_hint();
+            hint::spin_loop();
 
-            #[cfg(feature = "use_std")]
+            #[cfg(all(feature = "use_std", not(feature = "parking")))]
             ::std::thread::yield_now();
+
+            #[cfg(feature = "parking")]
+            wait_for_unlock(lock);
         }
         step = step.wrapping_add(1);
+    };
+
+    // Readers must not see any of the following writes without also seeing the lock as held.
+    atomic::fence(Ordering::Release);
+
+    #[cfg(all(debug_assertions, feature = "use_std"))]
+    owner.store(me, Ordering::Relaxed);
+
+    let seq_cst = order == Ordering::SeqCst;
+    if seq_cst {
+        atomic::fence(Ordering::SeqCst);
+    }
+
+    LockGuard {
+        lock,
+        stamp,
+        seq_cst,
+        #[cfg(all(debug_assertions, feature = "use_std"))]
+        owner,
     }
+}
+
+/// Parks the current thread until `lock` is released, unless it already has been.
+///
+/// The lock is marked as having parked waiters first, so the thread releasing it knows it has to
+/// wake them up. All of them are woken up and compete for the lock again, parking once more if
+/// they lose.
+#[cfg(feature = "parking")]
+#[cold]
+fn wait_for_unlock(lock: &'static atomic::AtomicUsize) {
+    let state = lock.load(Ordering::Relaxed);
+    if !is_locked(state) {
+        return;
+    }
+    if state == LOCKED
+        && lock
+            .compare_exchange(LOCKED, LOCKED_PARKED, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+    {
+        return;
+    }
+
+    // Releasing the lock swaps out `LOCKED_PARKED` before unparking, and both `validate` and
+    // unparking run with the wait queue locked, so a wakeup can't be missed.
+    ::parking::park(
+        lock as *const _ as usize,
+        || lock.load(Ordering::Relaxed) == LOCKED_PARKED,
+        None,
+    );
+}
+
+/// Reads atomic data stored at the given address using `read`, without blocking other readers.
+///
+/// The data is read while the lock is not held, and the read is only accepted if no writer has
+/// acquired the lock in the meantime. `read` may therefore observe a torn value, which is thrown
+/// away. Since the torn bytes need not be a valid value, `read` must only copy them into a
+/// `MaybeUninit`, which may be assumed to be initialized once this function returns. If the read
+/// fails, the lock is acquired just like for writing, so readers cannot be
+/// starved by a steady stream of writers.
+#[inline]
+unsafe fn read_optimistic<R, F>(addr: usize, order: Ordering, read: F) -> R
+where
+    F: Fn() -> R,
+{
+    let state = &LOCKS[lock_index(addr)];
+    let seq_cst = order == Ordering::SeqCst;
+
+    let stamp = state.load(Ordering::Acquire);
+    if !is_locked(stamp) {
+        if seq_cst {
+            atomic::fence(Ordering::SeqCst);
+        }
+
+        let val = read();
+
+        atomic::fence(Ordering::Acquire);
+        if state.load(Ordering::Relaxed) == stamp {
+            if seq_cst {
+                atomic::fence(Ordering::SeqCst);
+            }
+            return val;
+        }
+    }
+
+    let _lock = lock(addr, order);
+    read()
+}
+
+/// Returns the strongest failure ordering that is valid for a compare-and-exchange operation whose
+/// success ordering is `order`.
+#[inline]
+fn failure_ordering(order: Ordering) -> Ordering {
+    match order {
+        Ordering::Release => Ordering::Relaxed,
+        Ordering::AcqRel => Ordering::Acquire,
+        order => order,
+    }
+}
+
+/// Panics if `order` is not a valid ordering for a load.
+///
+/// The primitive atomic types panic on such orderings, so this check makes the lock-based fallback
+/// and zero-sized types behave consistently with them.
+#[inline]
+fn check_load_ordering(order: Ordering) {
+    match order {
+        Ordering::Release => panic!("there is no such thing as a release load"),
+        Ordering::AcqRel => panic!("there is no such thing as an acquire-release load"),
+        _ => {}
+    }
+}
+
+/// Panics if `order` is not a valid ordering for a store.
+#[inline]
+pub(crate) fn check_store_ordering(order: Ordering) {
+    match order {
+        Ordering::Acquire => panic!("there is no such thing as an acquire store"),
+        Ordering::AcqRel => panic!("there is no such thing as an acquire-release store"),
+        _ => {}
+    }
+}
+
+/// Panics if `failure` is not a valid failure ordering for a compare-and-exchange operation.
+#[inline]
+fn check_failure_ordering(failure: Ordering) {
+    match failure {
+        Ordering::Release => panic!("there is no such thing as a release failure ordering"),
+        Ordering::AcqRel => panic!("there is no such thing as an acquire-release failure ordering"),
+        _ => {}
+    }
+}
 
-    LockGuard { lock }
+/// Returns the stronger of the two orderings of a compare-and-exchange operation, which is the one
+/// the lock-based fallback has to honor.
+#[inline]
+fn strongest(success: Ordering, failure: Ordering) -> Ordering {
+    if success == Ordering::SeqCst || failure == Ordering::SeqCst {
+        Ordering::SeqCst
+    } else {
+        success
+    }
 }
 
 /// An atomic `()`.
@@ -561,7 +2232,26 @@ impl AtomicUnit {
     fn swap(&self, _val: (), _order: Ordering) {}
 
     #[inline]
-    fn compare_and_swap(&self, _current: (), _new: (), _order: Ordering) {}
+    fn compare_exchange(
+        &self,
+        _current: (),
+        _new: (),
+        _success: Ordering,
+        _failure: Ordering,
+    ) -> Result<(), ()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn compare_exchange_weak(
+        &self,
+        _current: (),
+        _new: (),
+        _success: Ordering,
+        _failure: Ordering,
+    ) -> Result<(), ()> {
+        Ok(())
+    }
 }
 
 macro_rules! atomic {
@@ -577,69 +2267,89 @@ macro_rules! atomic {
     // If values of type `$t` can be transmuted into values of a primitive atomic type, declares
     // variable `$a` of that type and executes `$atomic_op`. Otherwise, just executes
     // `$fallback_op`.
-    ($t:ty, $a:ident, $atomic_op:expr, $fallback_op:expr) => {
+    (@native $t:ty, $a:ident, $atomic_op:expr, $fallback_op:expr) => {
         loop {
             atomic!(@check, $t, AtomicUnit, $a, $atomic_op);
             atomic!(@check, $t, atomic::AtomicUsize, $a, $atomic_op);
 
-            #[cfg(feature = "nightly")]
-            {
-                #[cfg(target_has_atomic = "8")]
-                atomic!(@check, $t, atomic::AtomicU8, $a, $atomic_op);
-                #[cfg(target_has_atomic = "16")]
-                atomic!(@check, $t, atomic::AtomicU16, $a, $atomic_op);
-                #[cfg(target_has_atomic = "32")]
-                atomic!(@check, $t, atomic::AtomicU32, $a, $atomic_op);
-                #[cfg(target_has_atomic = "64")]
-                atomic!(@check, $t, atomic::AtomicU64, $a, $atomic_op);
-            }
+            #[cfg(target_has_atomic = "8")]
+            atomic!(@check, $t, atomic::AtomicU8, $a, $atomic_op);
+            #[cfg(target_has_atomic = "16")]
+            atomic!(@check, $t, atomic::AtomicU16, $a, $atomic_op);
+            #[cfg(target_has_atomic = "32")]
+            atomic!(@check, $t, atomic::AtomicU32, $a, $atomic_op);
+            #[cfg(target_has_atomic = "64")]
+            atomic!(@check, $t, atomic::AtomicU64, $a, $atomic_op);
 
             break $fallback_op
         }
     };
+
+    // Like the arm above, but 16-byte types use a 16-byte compare-and-swap if the CPU has one,
+    // before falling back to locks.
+    ($t:ty, $a:ident, $atomic_op:expr, $fallback_op:expr) => {
+        atomic!(@native $t, $a, $atomic_op, {
+            if can_transmute::<$t, AtomicDoubleWord>() && AtomicDoubleWord::is_supported() {
+                let $a: &AtomicDoubleWord;
+                $atomic_op
+            } else {
+                $fallback_op
+            }
+        })
+    };
 }
 
 /// Returns `true` if operations on `AtomicCell<T>` are lock-free.
-fn atomic_is_lock_free<T>() -> bool {
+pub(crate) fn atomic_is_lock_free<T>() -> bool {
     atomic! { T, _a, true, false }
 }
 
+/// Returns `true` if operations on `AtomicCell<T>` use a primitive atomic type of the same size as
+/// `T`, rather than a 16-byte compare-and-swap or a lock.
+fn atomic_is_native<T>() -> bool {
+    atomic! { @native T, _a, true, false }
+}
+
 /// Atomically reads data from `src`.
 ///
-/// This operation is sequentially consistent. If possible, an atomic instructions is used, and a
-/// global lock otherwise.
-unsafe fn atomic_load<T>(src: *mut T) -> T
+/// If possible, an atomic instruction with the given ordering is used, and a global lock
+/// otherwise.
+pub(crate) unsafe fn atomic_load<T>(src: *mut T, order: Ordering) -> T
 where
     T: Copy,
 {
+    check_load_ordering(order);
+
     atomic! {
         T, a,
         {
             a = &*(src as *const _ as *const _);
-            mem::transmute_copy(&a.load(Ordering::SeqCst))
+            mem::transmute_copy(&a.load(order))
         },
-        {
-            let _lock = lock(src as usize);
-            ptr::read(src)
-        }
+        read_optimistic(src as usize, order, || {
+            ptr::read_volatile(src as *const MaybeUninit<T>)
+        })
+        .assume_init()
     }
 }
 
 /// Atomically writes `val` to `dst`.
 ///
-/// This operation is sequentially consistent. If possible, an atomic instructions is used, and a
-/// global lock otherwise.
-unsafe fn atomic_store<T>(dst: *mut T, val: T) {
+/// If possible, an atomic instruction with the given ordering is used, and a global lock
+/// otherwise.
+pub(crate) unsafe fn atomic_store<T>(dst: *mut T, val: T, order: Ordering) {
+    check_store_ordering(order);
+
     atomic! {
         T, a,
         {
             a = &*(dst as *const _ as *const _);
-            let res = a.store(mem::transmute_copy(&val), Ordering::SeqCst);
+            let res = a.store(mem::transmute_copy(&val), order);
             mem::forget(val);
             res
         },
         {
-            let _lock = lock(dst as usize);
+            let _lock = lock(dst as usize, order);
             ptr::write(dst, val)
         }
     }
@@ -647,52 +2357,220 @@ unsafe fn atomic_store<T>(dst: *mut T, val: T) {
 
 /// Atomically swaps data at `dst` with `val`.
 ///
-/// This operation is sequentially consistent. If possible, an atomic instructions is used, and a
-/// global lock otherwise.
-unsafe fn atomic_swap<T>(dst: *mut T, val: T) -> T {
+/// If possible, an atomic instruction with the given ordering is used, and a global lock
+/// otherwise.
+pub(crate) unsafe fn atomic_swap<T>(dst: *mut T, val: T, order: Ordering) -> T {
     atomic! {
         T, a,
         {
             a = &*(dst as *const _ as *const _);
-            let res = mem::transmute_copy(&a.swap(mem::transmute_copy(&val), Ordering::SeqCst));
+            let res = mem::transmute_copy(&a.swap(mem::transmute_copy(&val), order));
             mem::forget(val);
             res
         },
         {
-            let _lock = lock(dst as usize);
+            let _lock = lock(dst as usize, order);
             ptr::replace(dst, val)
         }
     }
 }
 
-/// Atomically compares data at `dst` to `current` and, if equal byte-for-byte, swaps data at `dst`
+/// Atomically copies all bytes of data at `src` into `dst`, including any padding bytes.
+///
+/// Unlike a `T`, the copied bytes can be used as the comparand of [`atomic_compare_exchange_raw`]
+/// that is guaranteed to match if the data hasn't changed. Note that moving a `MaybeUninit<T>` by
+/// value doesn't necessarily preserve padding bytes either, so it must stay in place.
+///
+/// [`atomic_compare_exchange_raw`]: fn.atomic_compare_exchange_raw.html
+// Zero-sized types are handled by `AtomicUnit`, whose methods take and return `()`.
+#[allow(clippy::unit_arg)]
+pub(crate) unsafe fn atomic_load_raw<T>(src: *mut T, order: Ordering, dst: &mut MaybeUninit<T>)
+where
+    T: Copy,
+{
+    check_load_ordering(order);
+
+    atomic! {
+        T, a,
+        {
+            a = &*(src as *const _ as *const _);
+            ptr::write(dst.as_mut_ptr() as *mut _, a.load(order))
+        },
+        {
+            let dst = dst.as_mut_ptr();
+            read_optimistic(src as usize, order, || ptr::copy_nonoverlapping(src, dst, 1))
+        }
+    }
+}
+
+/// Atomically compares the bytes of data at `dst` to `current` and, if equal, swaps data at `dst`
 /// with `new`.
 ///
-/// This operation is sequentially consistent. If possible, an atomic instructions is used, and a
-/// global lock otherwise.
-unsafe fn atomic_compare_and_swap<T>(dst: *mut T, current: T, new: T) -> T
+/// Returns `true` on success. On failure, all bytes found at `dst` are copied into `current`. If
+/// `weak` is `true`, the operation is allowed to spuriously fail even when the comparison
+/// succeeds. If possible, an atomic instruction with the given orderings is used, and a global
+/// lock otherwise.
+// Zero-sized types are handled by `AtomicUnit`, whose methods take and return `()`.
+#[allow(clippy::unit_arg)]
+pub(crate) unsafe fn atomic_compare_exchange_raw<T>(
+    dst: *mut T,
+    current: &mut MaybeUninit<T>,
+    new: T,
+    success: Ordering,
+    failure: Ordering,
+    weak: bool,
+) -> bool
 where
     T: Copy,
 {
+    check_failure_ordering(failure);
+
     atomic! {
         T, a,
         {
             a = &*(dst as *const _ as *const _);
-            mem::transmute_copy(
-                &a.compare_and_swap(
-                    mem::transmute_copy(&current),
+            let res = if weak {
+                a.compare_exchange_weak(
+                    mem::transmute_copy(current),
                     mem::transmute_copy(&new),
-                    Ordering::SeqCst,
+                    success,
+                    failure,
                 )
-            )
+            } else {
+                a.compare_exchange(
+                    mem::transmute_copy(current),
+                    mem::transmute_copy(&new),
+                    success,
+                    failure,
+                )
+            };
+            match res {
+                Ok(_) => true,
+                Err(v) => {
+                    ptr::write(current.as_mut_ptr() as *mut _, v);
+                    false
+                }
+            }
         },
         {
-            let _lock = lock(dst as usize);
-            if byte_eq(&*dst, &current) {
-                ptr::replace(dst, new)
+            let _lock = lock(dst as usize, strongest(success, failure));
+            if byte_eq(&*(dst as *const MaybeUninit<T>), current) {
+                ptr::write(dst, new);
+                true
             } else {
-                ptr::read(dst)
+                ptr::copy_nonoverlapping(dst, current.as_mut_ptr(), 1);
+                false
             }
         }
     }
 }
+
+/// Atomically compares data at `dst` to `current` and, if equal byte-for-byte, swaps data at `dst`
+/// with `new`.
+///
+/// Returns the previous value on success, and the value found at `dst` on failure. If possible, an
+/// atomic instruction with the given orderings is used, and a global lock otherwise.
+pub(crate) unsafe fn atomic_compare_exchange<T>(
+    dst: *mut T,
+    current: T,
+    new: T,
+    success: Ordering,
+    failure: Ordering,
+) -> Result<T, T>
+where
+    T: Copy,
+{
+    let mut raw = MaybeUninit::new(current);
+    if atomic_compare_exchange_raw(dst, &mut raw, new, success, failure, false) {
+        Ok(raw.assume_init())
+    } else {
+        Err(raw.assume_init())
+    }
+}
+
+/// Atomically compares data at `dst` to `current` and, if equal byte-for-byte, swaps data at `dst`
+/// with `new`.
+///
+/// Unlike [`atomic_compare_exchange`], this function is allowed to spuriously fail even when the
+/// comparison succeeds, which can result in more efficient code on some platforms.
+///
+/// [`atomic_compare_exchange`]: fn.atomic_compare_exchange.html
+pub(crate) unsafe fn atomic_compare_exchange_weak<T>(
+    dst: *mut T,
+    current: T,
+    new: T,
+    success: Ordering,
+    failure: Ordering,
+) -> Result<T, T>
+where
+    T: Copy,
+{
+    let mut raw = MaybeUninit::new(current);
+    if atomic_compare_exchange_raw(dst, &mut raw, new, success, failure, true) {
+        Ok(raw.assume_init())
+    } else {
+        Err(raw.assume_init())
+    }
+}
+
+/// Atomically replaces data at `dst` with the result of applying `f` to it, until `f` returns
+/// `None` or the replacement succeeds.
+///
+/// Returns the previous and the new value on success, and the value `f` rejected otherwise. The
+/// comparand of each attempt consists of the exact bytes that were observed, so this never gets
+/// stuck on padding bytes. It is lock-free whenever `atomic_compare_exchange_raw` is.
+pub(crate) unsafe fn atomic_fetch_update<T, F>(
+    dst: *mut T,
+    success: Ordering,
+    failure: Ordering,
+    mut f: F,
+) -> Result<(T, T), T>
+where
+    T: Copy,
+    F: FnMut(T) -> Option<T>,
+{
+    let mut raw = MaybeUninit::uninit();
+    atomic_load_raw(dst, failure, &mut raw);
+
+    loop {
+        let current = ptr::read(raw.as_ptr());
+        let new = match f(current) {
+            Some(new) => new,
+            None => return Err(current),
+        };
+
+        if atomic_compare_exchange_raw(dst, &mut raw, new, success, failure, true) {
+            return Ok((current, new));
+        }
+    }
+}
+
+/// Atomically applies `f` to data at `dst` and returns the previous value.
+pub(crate) unsafe fn atomic_fetch_op<T, F>(dst: *mut T, order: Ordering, mut f: F) -> T
+where
+    T: Copy,
+    F: FnMut(T) -> T,
+{
+    match atomic_fetch_update(dst, order, failure_ordering(order), |x| Some(f(x))) {
+        Ok((previous, _)) => previous,
+        Err(_) => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(all(debug_assertions, feature = "use_std"))]
+    #[test]
+    #[should_panic(expected = "is already held by the current thread")]
+    fn relock_same_stripe() {
+        let a = 8;
+        let b = a + LOCK_STRIPES * 8;
+        assert_eq!(lock_index(a), lock_index(b));
+
+        // Waiting for a lock the current thread holds would spin forever.
+        let _guard = lock(a, Ordering::SeqCst);
+        let _ = lock(b, Ordering::SeqCst);
+    }
+}
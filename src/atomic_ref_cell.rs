@@ -0,0 +1,381 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::hint;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The borrow flag of a cell that is mutably borrowed.
+///
+/// All other values of the borrow state are the number of active shared borrows.
+const WRITING: usize = !(usize::MAX >> 1);
+
+/// A thread-safe mutable memory location with dynamically checked borrow rules.
+///
+/// This type is equivalent to [`RefCell`], except it can also be shared among multiple threads.
+///
+/// The whole borrow state is kept in a single atomic word: the highest bit is set while the cell
+/// is mutably borrowed, and the remaining bits count active shared borrows. Methods
+/// [`borrow`] and [`borrow_mut`] wait until the requested borrow can be acquired, while
+/// [`try_borrow`] and [`try_borrow_mut`] return an error instead of waiting.
+///
+/// [`RefCell`]: https://doc.rust-lang.org/std/cell/struct.RefCell.html
+/// [`borrow`]: struct.AtomicRefCell.html#method.borrow
+/// [`borrow_mut`]: struct.AtomicRefCell.html#method.borrow_mut
+/// [`try_borrow`]: struct.AtomicRefCell.html#method.try_borrow
+/// [`try_borrow_mut`]: struct.AtomicRefCell.html#method.try_borrow_mut
+pub struct AtomicRefCell<T: ?Sized> {
+    /// The borrow state.
+    ///
+    /// Either `WRITING` or the number of active shared borrows.
+    state: AtomicUsize,
+
+    /// The inner value.
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for AtomicRefCell<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for AtomicRefCell<T> {}
+
+impl<T> AtomicRefCell<T> {
+    /// Creates a new atomic reference cell initialized with `val`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::atomic_ref_cell::AtomicRefCell;
+    ///
+    /// let c = AtomicRefCell::new(7);
+    /// ```
+    pub fn new(val: T) -> AtomicRefCell<T> {
+        AtomicRefCell {
+            state: AtomicUsize::new(0),
+            value: UnsafeCell::new(val),
+        }
+    }
+
+    /// Unwraps the atomic reference cell and returns its inner value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::atomic_ref_cell::AtomicRefCell;
+    ///
+    /// let c = AtomicRefCell::new(7);
+    /// let v = c.into_inner();
+    ///
+    /// assert_eq!(v, 7);
+    /// ```
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> AtomicRefCell<T> {
+    /// Immutably borrows the inner value, waiting while it is mutably borrowed.
+    ///
+    /// The borrow lasts until the returned [`AtomicRef`] is dropped. Multiple immutable borrows
+    /// can be held at the same time.
+    ///
+    /// Note that waiting for a mutable borrow held by the current thread never finishes. Use
+    /// [`try_borrow`] if that can happen.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::atomic_ref_cell::AtomicRefCell;
+    ///
+    /// let c = AtomicRefCell::new(7);
+    ///
+    /// let b1 = c.borrow();
+    /// let b2 = c.borrow();
+    /// assert_eq!(*b1 + *b2, 14);
+    /// ```
+    ///
+    /// [`AtomicRef`]: struct.AtomicRef.html
+    /// [`try_borrow`]: struct.AtomicRefCell.html#method.try_borrow
+    pub fn borrow(&self) -> AtomicRef<'_, T> {
+        let mut step = 0usize;
+        loop {
+            if let Ok(r) = self.try_borrow() {
+                return r;
+            }
+            backoff(&mut step);
+        }
+    }
+
+    /// Immutably borrows the inner value, failing if it is currently mutably borrowed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::atomic_ref_cell::AtomicRefCell;
+    ///
+    /// let c = AtomicRefCell::new(7);
+    ///
+    /// {
+    ///     let m = c.borrow_mut();
+    ///     assert!(c.try_borrow().is_err());
+    /// }
+    ///
+    /// assert!(c.try_borrow().is_ok());
+    /// ```
+    pub fn try_borrow(&self) -> Result<AtomicRef<'_, T>, BorrowError> {
+        let mut state = self.state.load(Ordering::Relaxed);
+
+        loop {
+            if state & WRITING != 0 {
+                return Err(BorrowError { _private: () });
+            }
+
+            // Overflowing into the `WRITING` bit would let a mutable borrow alias these shared
+            // borrows, so this must be a hard error.
+            assert!(state + 1 != WRITING, "too many immutable borrows of an AtomicRefCell");
+
+            match self.state.compare_exchange_weak(
+                state,
+                state + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    return Ok(AtomicRef {
+                        value: unsafe { &*self.value.get() },
+                        state: &self.state,
+                    })
+                }
+                Err(s) => state = s,
+            }
+        }
+    }
+
+    /// Mutably borrows the inner value, waiting while it is borrowed.
+    ///
+    /// The borrow lasts until the returned [`AtomicRefMut`] is dropped.
+    ///
+    /// Note that waiting for a borrow held by the current thread never finishes. Use
+    /// [`try_borrow_mut`] if that can happen.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::atomic_ref_cell::AtomicRefCell;
+    ///
+    /// let c = AtomicRefCell::new(7);
+    ///
+    /// *c.borrow_mut() += 1;
+    /// assert_eq!(*c.borrow(), 8);
+    /// ```
+    ///
+    /// [`AtomicRefMut`]: struct.AtomicRefMut.html
+    /// [`try_borrow_mut`]: struct.AtomicRefCell.html#method.try_borrow_mut
+    pub fn borrow_mut(&self) -> AtomicRefMut<'_, T> {
+        let mut step = 0usize;
+        loop {
+            if let Ok(r) = self.try_borrow_mut() {
+                return r;
+            }
+            backoff(&mut step);
+        }
+    }
+
+    /// Mutably borrows the inner value, failing if it is currently borrowed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::atomic_ref_cell::AtomicRefCell;
+    ///
+    /// let c = AtomicRefCell::new(7);
+    ///
+    /// {
+    ///     let b = c.borrow();
+    ///     assert!(c.try_borrow_mut().is_err());
+    /// }
+    ///
+    /// assert!(c.try_borrow_mut().is_ok());
+    /// ```
+    pub fn try_borrow_mut(&self) -> Result<AtomicRefMut<'_, T>, BorrowMutError> {
+        match self
+            .state
+            .compare_exchange(0, WRITING, Ordering::Acquire, Ordering::Relaxed)
+        {
+            Ok(_) => Ok(AtomicRefMut {
+                value: unsafe { &mut *self.value.get() },
+                state: &self.state,
+            }),
+            Err(_) => Err(BorrowMutError { _private: () }),
+        }
+    }
+
+    /// Returns a raw pointer to the inner value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::atomic_ref_cell::AtomicRefCell;
+    ///
+    /// let c = AtomicRefCell::new(7);
+    /// let ptr = c.as_ptr();
+    /// ```
+    pub fn as_ptr(&self) -> *mut T {
+        self.value.get()
+    }
+
+    /// Returns a mutable reference to the inner value.
+    ///
+    /// No borrow checks are needed because the cell is borrowed mutably.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atomic::atomic_ref_cell::AtomicRefCell;
+    ///
+    /// let mut c = AtomicRefCell::new(7);
+    /// *c.get_mut() += 1;
+    ///
+    /// assert_eq!(*c.borrow(), 8);
+    /// ```
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.value.get() }
+    }
+}
+
+impl<T: Default> Default for AtomicRefCell<T> {
+    fn default() -> AtomicRefCell<T> {
+        AtomicRefCell::new(T::default())
+    }
+}
+
+impl<T> From<T> for AtomicRefCell<T> {
+    fn from(val: T) -> AtomicRefCell<T> {
+        AtomicRefCell::new(val)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for AtomicRefCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_borrow() {
+            Ok(r) => f.debug_struct("AtomicRefCell").field("value", &&*r).finish(),
+            Err(_) => f
+                .debug_struct("AtomicRefCell")
+                .field("value", &"<borrowed>")
+                .finish(),
+        }
+    }
+}
+
+/// A shared borrow of the value in an [`AtomicRefCell`].
+///
+/// [`AtomicRefCell`]: struct.AtomicRefCell.html
+pub struct AtomicRef<'a, T: ?Sized + 'a> {
+    value: &'a T,
+    state: &'a AtomicUsize,
+}
+
+impl<'a, T: ?Sized> Deref for AtomicRef<'a, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T: ?Sized> Drop for AtomicRef<'a, T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.state.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for AtomicRef<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+/// A mutable borrow of the value in an [`AtomicRefCell`].
+///
+/// [`AtomicRefCell`]: struct.AtomicRefCell.html
+pub struct AtomicRefMut<'a, T: ?Sized + 'a> {
+    value: &'a mut T,
+    state: &'a AtomicUsize,
+}
+
+impl<'a, T: ?Sized> Deref for AtomicRefMut<'a, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for AtomicRefMut<'a, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+impl<'a, T: ?Sized> Drop for AtomicRefMut<'a, T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.state.store(0, Ordering::Release);
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for AtomicRefMut<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+/// An error returned by [`AtomicRefCell::try_borrow`].
+///
+/// [`AtomicRefCell::try_borrow`]: struct.AtomicRefCell.html#method.try_borrow
+#[derive(Debug)]
+pub struct BorrowError {
+    _private: (),
+}
+
+impl fmt::Display for BorrowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        "already mutably borrowed".fmt(f)
+    }
+}
+
+#[cfg(feature = "use_std")]
+impl ::std::error::Error for BorrowError {}
+
+/// An error returned by [`AtomicRefCell::try_borrow_mut`].
+///
+/// [`AtomicRefCell::try_borrow_mut`]: struct.AtomicRefCell.html#method.try_borrow_mut
+#[derive(Debug)]
+pub struct BorrowMutError {
+    _private: (),
+}
+
+impl fmt::Display for BorrowMutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        "already borrowed".fmt(f)
+    }
+}
+
+#[cfg(feature = "use_std")]
+impl ::std::error::Error for BorrowMutError {}
+
+/// Waits a little before the next borrow attempt.
+#[inline]
+fn backoff(step: &mut usize) {
+    if *step < 10 {
+        hint::spin_loop();
+    } else {
+        #[cfg(not(feature = "use_std"))]
+        hint::spin_loop();
+
+        #[cfg(feature = "use_std")]
+        ::std::thread::yield_now();
+    }
+    *step = step.wrapping_add(1);
+}
@@ -1,3 +1,38 @@
+use std::num::{
+    NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU16, NonZeroU32,
+    NonZeroU64, NonZeroU8, NonZeroUsize,
+};
+use std::ptr::NonNull;
+
+/// Types whose equality is exactly byte-for-byte equality.
+///
+/// Atomic compare-and-swap instructions compare bytes, not values. For most types that is the same
+/// thing, but types with padding bytes or with several representations of the same value (like
+/// `-0.0` and `+0.0`) can compare equal while differing in their bytes. [`AtomicCell`] therefore
+/// has to retry its compare operations when the bytes differ but the values are equal. For types
+/// implementing this trait, a single byte-wise compare-and-swap gives the right answer, see
+/// [`AtomicCell::compare_exchange_bytes`].
+///
+/// The trait is implemented for:
+///
+/// * `()`, `bool`, `char` and all integer types,
+/// * the `NonZero*` integer types,
+/// * raw pointers, `NonNull<T>` and `Option<NonNull<T>>`,
+/// * tuples of up to twelve elements of the same `ByteEq` type,
+/// * arrays of up to 32 elements of a `ByteEq` type.
+///
+/// With the `derive` feature enabled, `#[derive(ByteEq)]` implements the trait for structs after
+/// checking at compile time that all fields are `ByteEq` and that there are no padding bytes. The
+/// struct's `PartialEq` can't be checked, so the derive also requires the
+/// `#[byte_eq(unsafe_assert_structural_eq)]` attribute, asserting that it compares all fields.
+///
+/// # Safety
+///
+/// Two values of the implementing type must be equal according to `Eq` if and only if all of
+/// their bytes are equal. In particular, the type must not contain any padding bytes.
+///
+/// [`AtomicCell`]: ../atomic_cell/struct.AtomicCell.html
+/// [`AtomicCell::compare_exchange_bytes`]: ../atomic_cell/struct.AtomicCell.html#method.compare_exchange_bytes
 pub unsafe trait ByteEq: Eq {}
 
 macro_rules! impl_primitive {
@@ -5,7 +40,25 @@ macro_rules! impl_primitive {
         $(unsafe impl ByteEq for $t {})*
     };
 }
-impl_primitive!((), bool, char, i8, u8, i16, u16, i32, u32, isize, usize);
+impl_primitive!((), bool, char);
+impl_primitive!(i8, u8, i16, u16, i32, u32, i64, u64, i128, u128, isize, usize);
+impl_primitive!(
+    NonZeroI8,
+    NonZeroU8,
+    NonZeroI16,
+    NonZeroU16,
+    NonZeroI32,
+    NonZeroU32,
+    NonZeroI64,
+    NonZeroU64,
+    NonZeroIsize,
+    NonZeroUsize
+);
+
+unsafe impl<T> ByteEq for *const T {}
+unsafe impl<T> ByteEq for *mut T {}
+unsafe impl<T> ByteEq for NonNull<T> {}
+unsafe impl<T> ByteEq for Option<NonNull<T>> {}
 
 macro_rules! impl_tuple {
     ($($i:ident),*) => {
@@ -38,3 +91,14 @@ impl_array!(
     17, 18, 19, 20, 21, 22, 23, 24,
     25, 26, 27, 28, 29, 30, 31, 32
 );
+
+/// Items used by code generated by `#[derive(ByteEq)]`. Not public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use std::mem::size_of;
+
+    use super::ByteEq;
+
+    #[inline]
+    pub fn assert_byte_eq<T: ByteEq>() {}
+}
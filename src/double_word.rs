@@ -0,0 +1,192 @@
+//! Lock-free operations on 16-byte values.
+//!
+//! There is no 128-bit atomic type in `std::sync::atomic`, so this module provides one on top of
+//! `cmpxchg16b` on x86_64 and `ldaxp`/`stlxp` on aarch64. Every operation is a full 128-bit
+//! compare-and-swap, which is sequentially consistent regardless of the requested ordering.
+
+use std::cell::UnsafeCell;
+use std::sync::atomic::Ordering;
+
+/// A 16-byte, 16-aligned atomic value.
+///
+/// The orderings passed to its methods are ignored, since every operation is a compare-and-swap
+/// that is sequentially consistent anyway. They are only there so that the type can be used in
+/// place of the primitive atomic types.
+#[repr(C, align(16))]
+pub(crate) struct AtomicDoubleWord {
+    v: UnsafeCell<u128>,
+}
+
+impl AtomicDoubleWord {
+    /// Returns `true` if the current CPU supports 16-byte compare-and-swap.
+    ///
+    /// The answer never changes while the program runs, so types can be dispatched on it just like
+    /// they are dispatched on their size and alignment.
+    #[inline]
+    pub(crate) fn is_supported() -> bool {
+        imp::is_supported()
+    }
+
+    #[inline]
+    pub(crate) fn load(&self, _order: Ordering) -> u128 {
+        // Exchanging zero for zero never changes the value, but returns it atomically.
+        match unsafe { imp::compare_exchange(self.v.get(), 0, 0) } {
+            Ok(v) | Err(v) => v,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn store(&self, val: u128, order: Ordering) {
+        self.swap(val, order);
+    }
+
+    #[inline]
+    pub(crate) fn swap(&self, val: u128, order: Ordering) -> u128 {
+        let mut current = self.load(order);
+        loop {
+            match unsafe { imp::compare_exchange(self.v.get(), current, val) } {
+                Ok(previous) => return previous,
+                Err(previous) => current = previous,
+            }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn compare_exchange(
+        &self,
+        current: u128,
+        new: u128,
+        _success: Ordering,
+        _failure: Ordering,
+    ) -> Result<u128, u128> {
+        unsafe { imp::compare_exchange(self.v.get(), current, new) }
+    }
+
+    #[inline]
+    pub(crate) fn compare_exchange_weak(
+        &self,
+        current: u128,
+        new: u128,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<u128, u128> {
+        self.compare_exchange(current, new, success, failure)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod imp {
+    use std::arch::asm;
+
+    #[cfg(feature = "use_std")]
+    #[inline]
+    pub fn is_supported() -> bool {
+        cfg!(target_feature = "cmpxchg16b") || is_x86_feature_detected!("cmpxchg16b")
+    }
+
+    #[cfg(not(feature = "use_std"))]
+    #[inline]
+    pub fn is_supported() -> bool {
+        cfg!(target_feature = "cmpxchg16b")
+    }
+
+    /// Compares the value at `dst` to `current` and, if equal, replaces it with `new`.
+    ///
+    /// The caller must make sure `cmpxchg16b` is supported and `dst` is 16-aligned.
+    #[inline]
+    #[target_feature(enable = "cmpxchg16b")]
+    pub unsafe fn compare_exchange(dst: *mut u128, current: u128, new: u128) -> Result<u128, u128> {
+        let (prev_lo, prev_hi): (u64, u64);
+
+        // `rbx` is reserved by LLVM and can't be an operand, so the low half of `new` is passed in
+        // a scratch register, swapped into `rbx` around the instruction and restored afterwards.
+        asm!(
+            "xchg {new_lo}, rbx",
+            "lock cmpxchg16b xmmword ptr [rdi]",
+            "mov rbx, {new_lo}",
+            in("rdi") dst,
+            new_lo = inout(reg) new as u64 => _,
+            in("rcx") (new >> 64) as u64,
+            inout("rax") current as u64 => prev_lo,
+            inout("rdx") (current >> 64) as u64 => prev_hi,
+            options(nostack),
+        );
+
+        let previous = (prev_hi as u128) << 64 | prev_lo as u128;
+        if previous == current {
+            Ok(previous)
+        } else {
+            Err(previous)
+        }
+    }
+}
+
+#[cfg(all(target_arch = "aarch64", target_endian = "little"))]
+mod imp {
+    use std::arch::asm;
+
+    #[inline]
+    pub fn is_supported() -> bool {
+        true
+    }
+
+    /// Compares the value at `dst` to `current` and, if equal, replaces it with `new`.
+    ///
+    /// The caller must make sure `dst` is 16-aligned.
+    #[inline]
+    pub unsafe fn compare_exchange(dst: *mut u128, current: u128, new: u128) -> Result<u128, u128> {
+        let (prev_lo, prev_hi): (u64, u64);
+
+        // A pair loaded by `ldaxp` is only guaranteed to be read atomically if the following
+        // `stlxp` succeeds, so on mismatch the loaded value is written back unchanged.
+        asm!(
+            "2:",
+            "ldaxp {prev_lo}, {prev_hi}, [{dst}]",
+            "cmp {prev_lo}, {cur_lo}",
+            "ccmp {prev_hi}, {cur_hi}, #0, eq",
+            "b.ne 3f",
+            "stlxp {res:w}, {new_lo}, {new_hi}, [{dst}]",
+            "cbnz {res:w}, 2b",
+            "b 4f",
+            "3:",
+            "stlxp {res:w}, {prev_lo}, {prev_hi}, [{dst}]",
+            "cbnz {res:w}, 2b",
+            "4:",
+            dst = in(reg) dst,
+            cur_lo = in(reg) current as u64,
+            cur_hi = in(reg) (current >> 64) as u64,
+            new_lo = in(reg) new as u64,
+            new_hi = in(reg) (new >> 64) as u64,
+            prev_lo = out(reg) prev_lo,
+            prev_hi = out(reg) prev_hi,
+            res = out(reg) _,
+            options(nostack),
+        );
+
+        let previous = (prev_hi as u128) << 64 | prev_lo as u128;
+        if previous == current {
+            Ok(previous)
+        } else {
+            Err(previous)
+        }
+    }
+}
+
+#[cfg(not(any(
+    target_arch = "x86_64",
+    all(target_arch = "aarch64", target_endian = "little")
+)))]
+mod imp {
+    #[inline]
+    pub fn is_supported() -> bool {
+        false
+    }
+
+    pub unsafe fn compare_exchange(
+        _dst: *mut u128,
+        _current: u128,
+        _new: u128,
+    ) -> Result<u128, u128> {
+        unreachable!("16-byte compare-and-swap is not supported on this target")
+    }
+}
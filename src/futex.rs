@@ -0,0 +1,41 @@
+//! Blocking threads on 32-bit values with the Linux futex syscall.
+
+use std::cmp;
+use std::mem;
+use std::ptr;
+use std::time::Duration;
+
+use libc;
+
+/// Blocks the current thread while the value at `futex` equals `expected`, for at most `timeout`.
+///
+/// The thread may also wake up spuriously, so the caller has to check the value again.
+pub unsafe fn wait(futex: *const u32, expected: u32, timeout: Option<Duration>) {
+    let ts = timeout.map(|dur| {
+        let mut ts: libc::timespec = mem::zeroed();
+        ts.tv_sec = cmp::min(dur.as_secs(), libc::time_t::MAX as u64) as libc::time_t;
+        ts.tv_nsec = dur.subsec_nanos() as libc::c_long;
+        ts
+    });
+    let ts = ts
+        .as_ref()
+        .map_or(ptr::null(), |ts| ts as *const libc::timespec);
+
+    libc::syscall(
+        libc::SYS_futex,
+        futex,
+        libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+        expected,
+        ts,
+    );
+}
+
+/// Wakes up at most `count` threads blocked on `futex`.
+pub unsafe fn wake(futex: *const u32, count: i32) {
+    libc::syscall(
+        libc::SYS_futex,
+        futex,
+        libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG,
+        count,
+    );
+}
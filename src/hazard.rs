@@ -1,143 +1,525 @@
+//! Hazard pointers protecting the values loaded from [`AtomicArc`] and [`AtomicBox`].
+//!
+//! Every thread that loads a value registers an entry with slots in a global registry, and
+//! publishes the address of the value in one of them for as long as it uses it. An entry grows
+//! when all of its slots are in use, and shrinks again once the slots it grew by are free, so
+//! holding many values at once doesn't slow down later scans.
+//!
+//! A value that has been displaced is retired instead of dropped: it is added to a list owned by
+//! the current thread. Once the list grows long enough, the thread scans all slots and drops the
+//! retired values nobody protects. The list is allowed to grow with the number of slots, so that
+//! each scan drops many values and the cost of a scan is spread over them. When a thread exits,
+//! the values it couldn't drop yet are handed over to the next thread that scans.
+//!
+//! Entries are freed when their thread exits, unless some slot is still in use, in which case they
+//! are freed by a later compaction of the registry. [`shutdown`] compacts the registry explicitly.
+//!
+//! Other lock-free data structures can use the same registry through [`HazardPointer`], which
+//! owns a slot, and [`retire`].
+//!
+//! [`AtomicArc`]: ../atomic_arc/struct.AtomicArc.html
+//! [`AtomicBox`]: ../atomic_box/struct.AtomicBox.html
+//! [`shutdown`]: fn.shutdown.html
+//! [`HazardPointer`]: struct.HazardPointer.html
+//! [`retire`]: fn.retire.html
+
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::cmp;
+use std::marker::PhantomData;
+use std::mem;
 use std::ptr;
-use std::sync::atomic::{self, AtomicUsize, AtomicPtr, AtomicBool, Ordering};
+use std::sync::atomic::{self, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 // TODO: if needs_drop is false, add retiring object to a freelist, and use sizeof to track memory
 
-pub fn allocate_slot() -> *const AtomicUsize {
+/// The minimum number of values a thread retires before it scans the slots.
+const RETIRE_THRESHOLD: usize = 64;
+
+/// The number of slots an entry starts with.
+const INITIAL_SLOTS: usize = 8;
+
+/// The value of a slot owned by a `HazardPointer` that doesn't protect anything.
+///
+/// Zero would mark the slot as free. No value can be stored at this address.
+const RESERVED: usize = usize::MAX;
+
+pub(crate) fn allocate_slot() -> *const AtomicUsize {
     LOCAL.with(|local| local.allocate_slot() as *const AtomicUsize)
 }
 
-#[derive(Default)]
-struct ThreadEntry {
-    slots: [AtomicUsize; 6],
-    next: AtomicPtr<ThreadEntry>,
-    in_use: AtomicBool,
+/// A hazard pointer, protecting a value loaded from an `AtomicPtr` from being dropped.
+///
+/// A hazard pointer owns a slot in the same registry that [`AtomicArc`] and [`AtomicBox`] use, so
+/// values retired with [`retire`] are dropped only once no hazard pointer protects them. It
+/// protects at most one value at a time, and gives its slot back when dropped.
+///
+/// # Examples
+///
+/// ```
+/// use atomic::hazard::{self, HazardPointer};
+/// use std::sync::atomic::{AtomicPtr, Ordering};
+///
+/// unsafe fn drop_box(ptr: *mut i32) {
+///     drop(Box::from_raw(ptr));
+/// }
+///
+/// let a = AtomicPtr::new(Box::into_raw(Box::new(7)));
+///
+/// let mut hp = HazardPointer::new();
+/// let guarded = hp.protect(&a);
+///
+/// let old = a.swap(Box::into_raw(Box::new(8)), Ordering::AcqRel);
+/// unsafe { hazard::retire(old, drop_box) };
+///
+/// // The old value is dropped only once the hazard pointer no longer protects it.
+/// hazard::collect();
+/// assert_eq!(unsafe { guarded.as_ref() }, Some(&7));
+/// hp.reset();
+///
+/// # unsafe { hazard::retire(a.load(Ordering::Relaxed), drop_box) };
+/// ```
+///
+/// [`AtomicArc`]: ../atomic_arc/struct.AtomicArc.html
+/// [`AtomicBox`]: ../atomic_box/struct.AtomicBox.html
+/// [`retire`]: fn.retire.html
+pub struct HazardPointer {
+    slot: *const AtomicUsize,
+}
+
+impl HazardPointer {
+    /// Creates a hazard pointer that doesn't protect anything.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called while the thread-local state of the current thread is being destroyed.
+    pub fn new() -> Self {
+        let slot = allocate_slot();
+        unsafe { (*slot).store(RESERVED, Ordering::Relaxed) };
+        HazardPointer { slot }
+    }
+
+    /// Loads a pointer from `src` and protects the value it points to.
+    ///
+    /// The value stays protected until the hazard pointer is reset, protects another value, or
+    /// is dropped. Any value protected before is released.
+    pub fn protect<T>(&mut self, src: &AtomicPtr<T>) -> Guarded<'_, T> {
+        let slot = self.slot();
+        let mut ptr = src.load(Ordering::Relaxed);
+
+        loop {
+            let value = if ptr.is_null() { RESERVED } else { ptr as usize };
+            slot.store(value, Ordering::Relaxed);
+
+            // Pairs with the fence in `reclaim`: either the pointer has been displaced from `src`
+            // and is loaded again, or the thread retiring it finds it in the slot.
+            atomic::fence(Ordering::SeqCst);
+
+            let new = src.load(Ordering::Acquire);
+            if new == ptr {
+                return Guarded {
+                    ptr,
+                    _marker: PhantomData,
+                };
+            }
+            ptr = new;
+        }
+    }
+
+    /// Releases the protected value, if any.
+    pub fn reset(&mut self) {
+        self.slot().store(RESERVED, Ordering::Release);
+    }
+
+    fn slot(&self) -> &AtomicUsize {
+        unsafe { &*self.slot }
+    }
 }
 
-#[derive(Default)]
-pub struct Registry {
-    entries: [ThreadEntry; 32],
-    next: AtomicPtr<Registry>,
+impl Default for HazardPointer {
+    fn default() -> Self {
+        HazardPointer::new()
+    }
 }
 
-static REGISTRY: AtomicPtr<Registry> = AtomicPtr::new(ptr::null_mut());
+impl Drop for HazardPointer {
+    fn drop(&mut self) {
+        self.slot().store(0, Ordering::Release);
+    }
+}
 
-fn try_extend_registry(ptr: &AtomicPtr<Registry>) {
-    let instance = Box::into_raw(Box::new(Registry::default()));
+/// A pointer protected by a [`HazardPointer`].
+///
+/// The value it points to can't be dropped through [`retire`] while the hazard pointer it has
+/// been loaded with is borrowed.
+///
+/// [`HazardPointer`]: struct.HazardPointer.html
+/// [`retire`]: fn.retire.html
+pub struct Guarded<'a, T: 'a> {
+    ptr: *mut T,
+    _marker: PhantomData<&'a T>,
+}
 
-    if ptr.compare_and_swap(ptr::null_mut(), instance, Ordering::SeqCst) != ptr::null_mut() {
-        // Some other thread has successfully extended Registry.
-        // It is our job now to delete `instance` we have just created.
-        unsafe { drop(Box::from_raw(instance)) }
+impl<'a, T> Guarded<'a, T> {
+    /// Returns the protected pointer.
+    pub fn as_ptr(&self) -> *mut T {
+        self.ptr
+    }
+
+    /// Returns a reference to the protected value, or `None` if the pointer is null.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be null or point to a valid value, which is only dropped through
+    /// [`retire`] after it has been removed from the `AtomicPtr` it was loaded from.
+    ///
+    /// [`retire`]: fn.retire.html
+    pub unsafe fn as_ref(&self) -> Option<&'a T> {
+        self.ptr.as_ref()
     }
 }
 
-#[inline]
-pub fn registry() -> &'static Registry {
-    let mut registry = REGISTRY.load(Ordering::Acquire);
+/// Drops the value at `ptr` using `deleter` once no hazard pointer protects it.
+///
+/// Null pointers are ignored. The deleter is called by the current thread or by another one,
+/// either from this function, from [`collect`], or from a later call to `retire`.
+///
+/// # Safety
+///
+/// The value must already be unreachable for threads that haven't protected it yet, for example
+/// because it has been swapped out of the `AtomicPtr` that held it, and it must not be retired
+/// twice. Calling `deleter` with `ptr` on any thread must be safe.
+///
+/// [`collect`]: fn.collect.html
+pub unsafe fn retire<T>(ptr: *mut T, deleter: unsafe fn(*mut T)) {
+    if ptr.is_null() {
+        return;
+    }
 
-    if registry.is_null() {
-        try_extend_registry(&REGISTRY);
-        registry = REGISTRY.load(Ordering::Acquire);
+    // Pointers to sized types are ABI-compatible, so the deleter can be called with the type
+    // erased.
+    let deleter = mem::transmute::<unsafe fn(*mut T), unsafe fn(*mut ())>(deleter);
+    let mut retired = Some(Retired {
+        ptr: ptr as usize,
+        deleter,
+    });
+    let _ = LOCAL.try_with(|local| local.retire(retired.take().unwrap()));
+
+    if let Some(retired) = retired {
+        // The thread is exiting and its list is gone.
+        abandon(reclaim(vec![retired]));
     }
+}
 
-    unsafe { &(*registry) }
+/// Drops the values retired by the current thread and by exited threads that are no longer
+/// protected.
+///
+/// This happens automatically once enough values have been retired, so it is only needed to make
+/// sure that a value that is no longer protected has been dropped, for example in tests.
+///
+/// # Examples
+///
+/// ```
+/// use atomic::{hazard, AtomicBox};
+///
+/// let a = AtomicBox::new(Box::new(7));
+/// let s = a.get();
+/// a.set(Box::new(8));
+///
+/// // The old value is still protected.
+/// hazard::collect();
+/// assert_eq!(s.as_ref(), Some(&7));
+///
+/// drop(s);
+/// hazard::collect();
+/// ```
+pub fn collect() {
+    // This might be called while thread-local variables are being destroyed.
+    if LOCAL.try_with(|local| local.collect()).is_err() {
+        abandon(reclaim(Vec::new()));
+    }
 }
 
-impl Registry {
-    #[cold]
-    pub fn destroy_object(&self, obj: usize) -> bool {
-        if obj == 0 {
-            false
-        } else {
-            !self.try_transfer_drop_responsibility(obj)
-        }
+/// Frees all memory held by the hazard pointer registry that is no longer needed.
+///
+/// The values retired by the current thread and by exited threads are dropped, unless they are
+/// still protected. The entry of the current thread is released, and the entries of all threads
+/// that have exited are freed. An entry with a slot still protecting a value stays around until
+/// the value is released. The current thread registers a new entry the next time it loads a
+/// value.
+///
+/// Returns `true` if nothing is left, which is the case at the end of a program or a test once all
+/// threads that used [`AtomicArc`] or [`AtomicBox`] have been joined and all loaded values have
+/// been dropped. Calling this function at that point makes sure leak checkers, such as Miri or
+/// LeakSanitizer, don't report the registry or retired values.
+///
+/// # Examples
+///
+/// ```
+/// use atomic::{hazard, AtomicArc};
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let a = Arc::new(AtomicArc::new(Arc::new(7)));
+///
+/// let b = a.clone();
+/// thread::spawn(move || assert_eq!(b.get().as_ref(), Some(&7)))
+///     .join()
+///     .unwrap();
+///
+/// assert_eq!(a.get().as_ref(), Some(&7));
+/// assert!(hazard::shutdown());
+/// ```
+///
+/// [`AtomicArc`]: ../atomic_arc/struct.AtomicArc.html
+/// [`AtomicBox`]: ../atomic_box/struct.AtomicBox.html
+pub fn shutdown() -> bool {
+    // This might be called while thread-local variables are being destroyed.
+    let _ = LOCAL.try_with(|local| local.release());
+
+    // Reclaiming adopts the values abandoned by exited threads.
+    let left = reclaim(Vec::new());
+    let reclaimed = left.is_empty();
+    abandon(left);
+
+    let mut entries = registry().write();
+    compact(&mut entries);
+    reclaimed && entries.is_empty()
+}
+
+/// A value waiting to be dropped.
+struct Retired {
+    ptr: usize,
+    deleter: unsafe fn(*mut ()),
+}
+
+/// Values retired by threads that exited before they could drop them.
+static ABANDONED: Mutex<Vec<Retired>> = Mutex::new(Vec::new());
+
+fn abandoned() -> MutexGuard<'static, Vec<Retired>> {
+    // Deleters run without holding the lock, so it can't be poisoned by them.
+    ABANDONED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Hands values over to the next thread that scans the slots.
+fn abandon(mut retired: Vec<Retired>) {
+    if !retired.is_empty() {
+        abandoned().append(&mut retired);
     }
+}
 
-    fn register(&self) -> *const ThreadEntry {
-        for entry in self.entries.iter() {
-            if !entry.in_use.load(Ordering::SeqCst) {
-                if !entry.in_use.swap(true, Ordering::SeqCst) {
-                    return entry;
-                }
-            }
-        }
+/// Drops the retired values that aren't protected by any slot, and returns the others.
+///
+/// Values abandoned by exited threads are taken over and reclaimed, too.
+fn reclaim(mut retired: Vec<Retired>) -> Vec<Retired> {
+    retired.append(&mut abandoned());
+    if retired.is_empty() {
+        return retired;
+    }
 
-        let mut next = self.next.load(Ordering::SeqCst);
+    // Pairs with the fences in `AtomicArc::get` and `HazardPointer::protect`: either the reader
+    // sees that the value has been displaced, or the value is found in its slot.
+    atomic::fence(Ordering::SeqCst);
+    let hazards = registry().hazards();
 
-        if next.is_null() {
-            try_extend_registry(&self.next);
-            next = self.next.load(Ordering::SeqCst);
-        }
+    let (protected, unprotected): (Vec<_>, Vec<_>) = retired
+        .into_iter()
+        .partition(|r| hazards.binary_search(&r.ptr).is_ok());
+
+    // Dropping a value might retire more values, so no locks are held here.
+    for r in unprotected {
+        unsafe { (r.deleter)(r.ptr as *mut ()) };
+    }
+    protected
+}
+
+struct ThreadEntry {
+    /// Blocks of slots, each as large as all blocks before it.
+    ///
+    /// Only the owning thread adds and removes blocks, and only while holding the write lock of
+    /// the registry, so other threads can read them while holding the read lock.
+    blocks: UnsafeCell<Vec<Box<[AtomicUsize]>>>,
+
+    /// Whether the owning thread is still running.
+    in_use: AtomicBool,
+}
+
+unsafe impl Sync for ThreadEntry {}
+
+/// Entries are boxed so that they don't move while threads use them.
+#[allow(clippy::vec_box)]
+type Entries = Vec<Box<ThreadEntry>>;
+
+struct Registry {
+    /// The entries of running threads, and of exited threads with slots still in use.
+    ///
+    /// Scanning the slots takes a read lock, so entries can be freed while holding a write lock.
+    /// Threads only take the write lock when they start or exit, or when their entry grows or
+    /// shrinks.
+    entries: RwLock<Entries>,
+}
+
+static REGISTRY: Registry = Registry {
+    entries: RwLock::new(Vec::new()),
+};
+
+#[inline]
+fn registry() -> &'static Registry {
+    &REGISTRY
+}
+
+/// Frees all entries of exited threads whose slots are no longer in use.
+///
+/// A zero slot only becomes non-zero when the thread owning the entry protects a value with it,
+/// so once the slots of an exited thread are all zero they stay that way.
+fn compact(entries: &mut Entries) {
+    entries.retain(|entry| entry.in_use.load(Ordering::Relaxed) || !entry.is_clear());
+
+    if entries.is_empty() {
+        // Give the buffer back, too.
+        *entries = Vec::new();
+    }
+}
+
+impl Registry {
+    // Nothing panics while the lock is held, but poisoning would make every later operation on
+    // `AtomicArc` and `AtomicBox` panic, so it's ignored.
 
-        unsafe { (*next).register() }
+    fn read(&self) -> RwLockReadGuard<'_, Entries> {
+        self.entries.read().unwrap_or_else(|e| e.into_inner())
     }
 
-    fn try_transfer_drop_responsibility(&self, ptr: usize) -> bool {
-        debug_assert_ne!(ptr, 0);
+    fn write(&self) -> RwLockWriteGuard<'_, Entries> {
+        self.entries.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn register(&self) -> *const ThreadEntry {
+        let entry = Box::new(ThreadEntry::new());
+        entry.in_use.store(true, Ordering::Relaxed);
+
+        let ptr = &*entry as *const ThreadEntry;
+        self.write().push(entry);
+        ptr
+    }
+
+    fn unregister(&self, entry: *const ThreadEntry) {
+        let mut entries = self.write();
+        unsafe { (*entry).in_use.store(false, Ordering::Relaxed) };
+        compact(&mut entries);
+    }
 
-        atomic::fence(Ordering::SeqCst);
+    /// Returns the sorted values of all slots in use.
+    fn hazards(&self) -> Vec<usize> {
+        let mut hazards = Vec::new();
 
-        for entry in self.entries.iter() {
-            if entry.in_use.load(Ordering::Acquire) {
-                if entry.try_transfer_drop_responsibility(ptr) {
-                    return true;
+        // Entries of exited threads are scanned, too, since their slots might still be in use.
+        for entry in self.read().iter() {
+            for slot in entry.slots() {
+                let ptr = slot.load(Ordering::Acquire);
+                if ptr != 0 {
+                    hazards.push(ptr);
                 }
             }
         }
 
-        unsafe {
-            let next = self.next.load(Ordering::Acquire);
+        hazards.sort_unstable();
+        hazards
+    }
 
-            if !next.is_null() {
-                (*next).try_transfer_drop_responsibility(ptr)
-            } else {
-                false
-            }
-        }
+    /// Returns the number of slots of all entries.
+    fn slot_count(&self) -> usize {
+        self.read().iter().map(|entry| entry.slot_count()).sum()
     }
 }
 
 impl ThreadEntry {
-    fn unregister(&self) {
-        self.in_use.store(false, Ordering::SeqCst)
+    fn new() -> Self {
+        ThreadEntry {
+            blocks: UnsafeCell::new(vec![new_block(INITIAL_SLOTS)]),
+            in_use: AtomicBool::new(false),
+        }
     }
 
-    #[inline]
-    fn allocate_slot(&self) -> *const AtomicUsize {
-        for slot in self.slots.iter() {
-            if slot.load(Ordering::Relaxed) == 0 {
-                return slot;
-            }
-        }
+    /// Returns the blocks of slots.
+    ///
+    /// Must be called by the owning thread, or while holding a lock of the registry.
+    fn blocks(&self) -> &[Box<[AtomicUsize]>] {
+        unsafe { &*self.blocks.get() }
+    }
 
-        let mut next = self.next.load(Ordering::SeqCst);
+    fn slots(&self) -> impl Iterator<Item = &AtomicUsize> {
+        self.blocks().iter().flat_map(|block| block.iter())
+    }
 
-        if next.is_null() {
-            let new_entry = Box::into_raw(Box::new(ThreadEntry::default()));
-            self.next.store(new_entry, Ordering::SeqCst);
-            next = new_entry;
-        }
+    fn slot_count(&self) -> usize {
+        self.blocks().iter().map(|block| block.len()).sum()
+    }
 
-        unsafe { (*next).allocate_slot() }
+    /// Returns `true` if none of the slots are in use.
+    fn is_clear(&self) -> bool {
+        self.slots().all(|slot| slot.load(Ordering::Relaxed) == 0)
     }
 
-    fn try_transfer_drop_responsibility(&self, ptr: usize) -> bool {
-        debug_assert_ne!(ptr, 0);
+    /// Adds a block doubling the number of slots, and returns it.
+    ///
+    /// Must be called by the owning thread.
+    fn grow(&self) -> &[AtomicUsize] {
+        let block = new_block(self.slot_count());
 
-        for slot in self.slots.iter() {
-            if slot.load(Ordering::SeqCst) == ptr {
-                if slot.compare_and_swap(ptr, 1, Ordering::SeqCst) == ptr {
-                    return true;
-                }
-            }
+        let _entries = registry().write();
+        let blocks = unsafe { &mut *self.blocks.get() };
+        blocks.push(block);
+        blocks.last().unwrap()
+    }
+
+    /// Frees the blocks the entry has grown by, as long as their slots aren't in use. Returns
+    /// `true` if any block has been freed.
+    ///
+    /// Must be called by the owning thread.
+    fn shrink(&self) -> bool {
+        let is_clear = |block: &[AtomicUsize]| {
+            block.iter().all(|slot| slot.load(Ordering::Relaxed) == 0)
+        };
+
+        // The first block is never freed. Later ones are freed from the last, since the slots
+        // of earlier blocks are handed out first.
+        let blocks = self.blocks();
+        let keep = blocks.len()
+            - blocks[1..]
+                .iter()
+                .rev()
+                .take_while(|block| is_clear(block))
+                .count();
+        if keep == blocks.len() {
+            return false;
         }
-        return false;
+
+        let _entries = registry().write();
+        unsafe { (*self.blocks.get()).truncate(keep) };
+        true
     }
 }
 
+fn new_block(len: usize) -> Box<[AtomicUsize]> {
+    (0..len).map(|_| AtomicUsize::new(0)).collect()
+}
+
+/// The state of the current thread.
 struct Local {
-    entry: *const ThreadEntry,
+    /// The entry of the thread, registered the first time it is needed.
+    entry: Cell<*const ThreadEntry>,
+
+    /// Slots of the entry that are free, with the ones to hand out first at the end.
+    ///
+    /// Slots are freed by zeroing them, without going through this list, so it is refilled by
+    /// looking for zero slots once it runs empty.
+    free: RefCell<Vec<*const AtomicUsize>>,
+
+    /// Values retired by the thread.
+    retired: RefCell<Vec<Retired>>,
+
+    /// The length `retired` has to reach before the slots are scanned again.
+    threshold: Cell<usize>,
 }
 
 thread_local! {
@@ -145,20 +527,105 @@ thread_local! {
 }
 
 impl Local {
-    pub fn new() -> Self {
+    fn new() -> Self {
         Local {
-            entry: registry().register(),
+            entry: Cell::new(ptr::null()),
+            free: RefCell::new(Vec::new()),
+            retired: RefCell::new(Vec::new()),
+            threshold: Cell::new(RETIRE_THRESHOLD),
         }
     }
 
     #[inline]
     fn allocate_slot(&self) -> &AtomicUsize {
-        unsafe { &*(*self.entry).allocate_slot() }
+        if let Some(slot) = self.free.borrow_mut().pop() {
+            return unsafe { &*slot };
+        }
+        self.refill()
+    }
+
+    /// Refills the list of free slots, and returns one of them.
+    #[cold]
+    fn refill(&self) -> &AtomicUsize {
+        let mut entry = self.entry.get();
+        if entry.is_null() {
+            entry = registry().register();
+            self.entry.set(entry);
+        }
+        let entry = unsafe { &*entry };
+
+        let mut free = self.free.borrow_mut();
+        free.extend(
+            entry
+                .slots()
+                .filter(|slot| slot.load(Ordering::Relaxed) == 0)
+                .map(|slot| slot as *const AtomicUsize),
+        );
+
+        // Growing the entry once at most half of the slots are free makes sure that refilling
+        // costs constant time per slot handed out.
+        if free.len() <= entry.slot_count() / 2 {
+            free.extend(entry.grow().iter().map(|slot| slot as *const AtomicUsize));
+        }
+
+        free.reverse();
+        unsafe { &*free.pop().unwrap() }
+    }
+
+    #[inline]
+    fn retire(&self, retired: Retired) {
+        let len = {
+            let mut list = self.retired.borrow_mut();
+            list.push(retired);
+            list.len()
+        };
+
+        if len >= self.threshold.get() {
+            self.collect();
+        }
+    }
+
+    #[cold]
+    fn collect(&self) {
+        // Slots freed after holding many values at once are given back, so that they don't slow
+        // down every scan from now on.
+        let entry = self.entry.get();
+        if !entry.is_null() && unsafe { (*entry).shrink() } {
+            // The list might point into freed blocks.
+            self.free.borrow_mut().clear();
+        }
+
+        let retired = mem::take(&mut *self.retired.borrow_mut());
+        let left = reclaim(retired);
+
+        // A scan costs time proportional to the number of slots, and at most that many values can
+        // be left over, so waiting for as many new values keeps the cost per value constant.
+        let threshold = left.len() + cmp::max(RETIRE_THRESHOLD, registry().slot_count());
+        self.threshold.set(threshold);
+
+        // Deleters might have retired more values in the meantime.
+        self.retired.borrow_mut().extend(left);
+    }
+
+    /// Reclaims the retired values and gives the entry back to the registry.
+    fn release(&self) {
+        let retired = mem::take(&mut *self.retired.borrow_mut());
+        abandon(reclaim(retired));
+
+        self.free.borrow_mut().clear();
+        let entry = self.entry.replace(ptr::null());
+        if !entry.is_null() {
+            registry().unregister(entry);
+        }
     }
 }
 
 impl Drop for Local {
     fn drop(&mut self) {
-        unsafe { (*self.entry).unregister() }
+        // Guards stored in other thread-local variables may outlive this one. Their slots are
+        // still set, so the values they protect are handed over to other threads, and the entry
+        // is kept in the registry until the guards are dropped and have zeroed the slots. Only
+        // then is it freed by a compaction.
+        self.release();
     }
 }
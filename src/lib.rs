@@ -1,25 +1,38 @@
 #![cfg_attr(not(feature = "use_std"), no_std)]
-#![cfg_attr(feature = "nightly", feature(cfg_target_has_atomic, integer_atomics))]
-
-#[macro_use]
-extern crate cfg_if;
 
 #[cfg(not(feature = "use_std"))]
 extern crate core as std;
 
 extern crate crossbeam;
+#[cfg(all(feature = "use_std", target_os = "linux"))]
+extern crate libc;
+
+#[cfg(feature = "derive")]
+extern crate atomic_derive;
 
+mod double_word;
+#[cfg(all(feature = "use_std", target_os = "linux"))]
+mod futex;
 #[cfg(feature = "use_std")]
-mod hazard;
+mod parking;
 
 pub mod atomic;
-// #[cfg(feature = "use_std")]
-// pub mod atomic_box;
 #[cfg(feature = "use_std")]
 pub mod atomic_arc;
+#[cfg(feature = "use_std")]
+pub mod atomic_box;
 pub mod atomic_cell;
 pub mod atomic_ref_cell;
+pub mod byte_eq;
+#[cfg(feature = "use_std")]
+pub mod hazard;
 
-// pub use atomic_box::AtomicBox;
-pub use atomic_cell::AtomicCell;
+pub use atomic::Atomic;
+#[cfg(feature = "use_std")]
 pub use atomic_arc::AtomicArc;
+#[cfg(feature = "use_std")]
+pub use atomic_box::AtomicBox;
+pub use atomic_cell::AtomicCell;
+pub use byte_eq::ByteEq;
+#[cfg(feature = "derive")]
+pub use atomic_derive::ByteEq;
@@ -0,0 +1,139 @@
+//! Blocking threads on arbitrary addresses.
+//!
+//! Threads are parked in a global table of wait queues, picked based on the address they wait on,
+//! so no per-address data needs to be allocated up front. This is the same idea as the table of
+//! locks used by `AtomicCell`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, Thread};
+use std::time::Instant;
+
+/// The number of wait queues is prime.
+const BUCKETS: usize = 61;
+
+/// A thread parked on an address.
+struct Waiter {
+    /// The address the thread is waiting on.
+    key: usize,
+
+    /// The parked thread.
+    thread: Thread,
+
+    /// Set when the thread has been removed from the queue and may return.
+    unparked: AtomicBool,
+}
+
+/// A queue of parked threads.
+struct Bucket {
+    waiters: Mutex<Vec<Arc<Waiter>>>,
+}
+
+impl Bucket {
+    fn lock(&self) -> MutexGuard<'_, Vec<Arc<Waiter>>> {
+        // Nothing can panic while the queue is locked, but there's no reason to give up if that
+        // ever changes.
+        self.waiters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+static QUEUES: [Bucket; BUCKETS] = [const {
+    Bucket {
+        waiters: Mutex::new(Vec::new()),
+    }
+}; BUCKETS];
+
+fn bucket(key: usize) -> &'static Bucket {
+    &QUEUES[key % BUCKETS]
+}
+
+/// Parks the current thread on `key` until it is unparked or `deadline` passes.
+///
+/// `validate` is called while the wait queue is locked, and the thread is only parked if it
+/// returns `true`. Since unparking locks the same queue, a thread that unparks `key` after making
+/// `validate` return `false` never misses a waiter.
+///
+/// Returns `true` if the thread was unparked, and `false` if it was not parked because `validate`
+/// returned `false` or if it timed out.
+pub fn park<F>(key: usize, validate: F, deadline: Option<Instant>) -> bool
+where
+    F: FnOnce() -> bool,
+{
+    let waiter = Arc::new(Waiter {
+        key,
+        thread: thread::current(),
+        unparked: AtomicBool::new(false),
+    });
+
+    {
+        let mut waiters = bucket(key).lock();
+        if !validate() {
+            return false;
+        }
+        waiters.push(waiter.clone());
+    }
+
+    // `thread::park` can wake up spuriously.
+    while !waiter.unparked.load(Ordering::Acquire) {
+        match deadline {
+            None => thread::park(),
+            Some(deadline) => {
+                let now = Instant::now();
+                if now < deadline {
+                    thread::park_timeout(deadline - now);
+                    continue;
+                }
+
+                let mut waiters = bucket(key).lock();
+                if let Some(i) = waiters.iter().position(|w| Arc::ptr_eq(w, &waiter)) {
+                    waiters.remove(i);
+                    return false;
+                }
+
+                // Another thread has already removed us from the queue and is about to set
+                // `unparked`, so the wakeup is not lost.
+                drop(waiters);
+                while !waiter.unparked.load(Ordering::Acquire) {
+                    thread::park();
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Unparks the thread that has been parked on `key` the longest, and returns `true` if there was
+/// one.
+pub fn unpark_one(key: usize) -> bool {
+    let waiter = {
+        let mut waiters = bucket(key).lock();
+        match waiters.iter().position(|w| w.key == key) {
+            Some(i) => waiters.remove(i),
+            None => return false,
+        }
+    };
+
+    wake(&waiter);
+    true
+}
+
+/// Unparks all threads parked on `key`, and returns how many there were.
+pub fn unpark_all(key: usize) -> usize {
+    let unparked: Vec<_> = {
+        let mut waiters = bucket(key).lock();
+        let (unparked, rest) = waiters.drain(..).partition(|w| w.key == key);
+        *waiters = rest;
+        unparked
+    };
+
+    for waiter in &unparked {
+        wake(waiter);
+    }
+    unparked.len()
+}
+
+/// Lets a thread removed from its wait queue return from `park`.
+fn wake(waiter: &Waiter) {
+    waiter.unparked.store(true, Ordering::Release);
+    waiter.thread.unpark();
+}
@@ -1,6 +1,169 @@
 extern crate atomic;
+extern crate crossbeam;
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release, SeqCst};
+
+use atomic::Atomic;
+
+#[test]
+fn is_lock_free() {
+    assert!(Atomic::<usize>::is_lock_free());
+    assert!(Atomic::<isize>::is_lock_free());
+    assert!(Atomic::<()>::is_lock_free());
+    assert!(!Atomic::<[u8; 1000]>::is_lock_free());
+}
+
+#[test]
+fn load_store_swap() {
+    let a = Atomic::new(1usize);
+
+    assert_eq!(a.load(Relaxed), 1);
+    a.store(2, Release);
+    assert_eq!(a.load(Acquire), 2);
+    assert_eq!(a.swap(3, AcqRel), 2);
+    assert_eq!(a.load(SeqCst), 3);
+}
+
+#[test]
+fn load_store_swap_locked() {
+    let a = Atomic::new([1u8; 1000]);
+
+    assert_eq!(a.load(Relaxed)[..], [1u8; 1000][..]);
+    a.store([2; 1000], Release);
+    assert_eq!(a.load(Acquire)[..], [2u8; 1000][..]);
+    assert_eq!(a.swap([3; 1000], AcqRel)[..], [2u8; 1000][..]);
+    assert_eq!(a.into_inner()[..], [3u8; 1000][..]);
+}
+
+#[test]
+fn compare_exchange() {
+    let a = Atomic::new(7usize);
+
+    assert_eq!(a.compare_exchange(1, 8, AcqRel, Acquire), Err(7));
+    assert_eq!(a.compare_exchange(7, 8, AcqRel, Acquire), Ok(7));
+    assert_eq!(a.load(Relaxed), 8);
+
+    let b = Atomic::new((1u64, 2u64, 3u64));
+
+    assert_eq!(
+        b.compare_exchange((0, 0, 0), (4, 5, 6), SeqCst, SeqCst),
+        Err((1, 2, 3))
+    );
+    assert_eq!(
+        b.compare_exchange((1, 2, 3), (4, 5, 6), SeqCst, SeqCst),
+        Ok((1, 2, 3))
+    );
+    assert_eq!(b.load(SeqCst), (4, 5, 6));
+}
+
+#[test]
+fn compare_exchange_weak() {
+    let a = Atomic::new(7usize);
+
+    let mut current = a.load(Relaxed);
+    loop {
+        match a.compare_exchange_weak(current, current + 1, Release, Relaxed) {
+            Ok(_) => break,
+            Err(x) => current = x,
+        }
+    }
+    assert_eq!(a.load(Relaxed), 8);
+
+    assert_eq!(a.compare_exchange_weak(1, 2, SeqCst, SeqCst), Err(8));
+}
 
 #[test]
-fn smoke() {
-    // TODO
+fn drops() {
+    static CNT: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Foo(usize);
+
+    impl Drop for Foo {
+        fn drop(&mut self) {
+            CNT.fetch_add(1, SeqCst);
+        }
+    }
+
+    let a = Atomic::new(Foo(1));
+
+    assert_eq!(a.swap(Foo(2), SeqCst).0, 1);
+    assert_eq!(CNT.load(SeqCst), 1);
+
+    a.store(Foo(3), SeqCst);
+    assert_eq!(CNT.load(SeqCst), 2);
+
+    drop(a);
+    assert_eq!(CNT.load(SeqCst), 3);
+}
+
+#[test]
+#[should_panic(expected = "there is no such thing as an acquire store")]
+fn acquire_store() {
+    Atomic::new(7u64).store(8, Acquire);
+}
+
+#[test]
+#[should_panic(expected = "there is no such thing as an acquire store")]
+fn acquire_store_needs_drop() {
+    Atomic::new(String::new()).store(String::new(), Acquire);
+}
+
+#[test]
+fn message_passing() {
+    const N: usize = 10_000;
+
+    for _ in 0..10 {
+        let data = Atomic::new([0usize; 4]);
+        let flag = Atomic::new(false);
+
+        crossbeam::scope(|s| {
+            s.spawn(|| {
+                data.store([N; 4], Relaxed);
+                flag.store(true, Release);
+            });
+            s.spawn(|| {
+                while !flag.load(Acquire) {}
+                assert_eq!(data.load(Relaxed), [N; 4]);
+            });
+        });
+    }
+}
+
+#[test]
+fn concurrent_increments() {
+    const THREADS: usize = 8;
+    const STEPS: usize = 10_000;
+
+    let a = Atomic::new(0usize);
+    let b = Atomic::new((0usize, 0usize));
+
+    crossbeam::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..STEPS {
+                    let mut current = a.load(Relaxed);
+                    while let Err(x) =
+                        a.compare_exchange_weak(current, current + 1, AcqRel, Relaxed)
+                    {
+                        current = x;
+                    }
+
+                    let mut current = b.load(Relaxed);
+                    while let Err(x) = b.compare_exchange_weak(
+                        current,
+                        (current.0 + 1, current.1 + 2),
+                        AcqRel,
+                        Relaxed,
+                    ) {
+                        current = x;
+                    }
+                }
+            });
+        }
+    });
+
+    assert_eq!(a.into_inner(), THREADS * STEPS);
+    assert_eq!(b.into_inner(), (THREADS * STEPS, 2 * THREADS * STEPS));
 }
@@ -3,7 +3,7 @@ extern crate crossbeam;
 
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use atomic::AtomicArc;
+use atomic::{hazard, AtomicArc};
 
 static DROP_PER_THREAD: usize = 1000000;
 static N_THREADS: usize = 8;
@@ -36,6 +36,129 @@ fn test_replace() {
         }
     });
 
+    hazard::collect();
     assert_eq!(DROP_CNT.load(Ordering::Relaxed), N_THREADS * DROP_PER_THREAD);
 }
 
+#[test]
+fn short_lived_threads() {
+    const BATCHES: usize = 250;
+    const THREADS: usize = 16;
+
+    static CREATED: AtomicUsize = AtomicUsize::new(0);
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Bar;
+
+    impl Bar {
+        fn new() -> Arc<Bar> {
+            CREATED.fetch_add(1, Ordering::SeqCst);
+            Arc::new(Bar)
+        }
+    }
+
+    impl Drop for Bar {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let cell = AtomicArc::new(Bar::new());
+
+    for _ in 0..BATCHES {
+        crossbeam::scope(|s| {
+            for i in 0..THREADS {
+                let cell = &cell;
+                s.spawn(move || {
+                    // Some threads exit while holding more guards than fit into one entry.
+                    let guards: Vec<_> = (0..i).map(|_| cell.get()).collect();
+                    cell.replace(Bar::new());
+                    cell.set(if i % 2 == 0 { None } else { Some(Bar::new()) });
+                    drop(cell.get());
+                    drop(guards);
+                });
+            }
+        });
+    }
+
+    drop(cell);
+    hazard::collect();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), CREATED.load(Ordering::SeqCst));
+}
+
+#[test]
+fn guard_outlives_thread_entry() {
+    use std::thread;
+
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Bar;
+
+    impl Drop for Bar {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let cell = Arc::new(AtomicArc::new(Arc::new(Bar)));
+    let c = cell.clone();
+
+    thread::spawn(move || {
+        let held = c.get();
+
+        // The held value is displaced but still protected.
+        c.set(None);
+        hazard::collect();
+        assert_eq!(DROPPED.load(Ordering::SeqCst), 0);
+
+        // Releasing the thread's entry while the guard is alive, like the thread-local destructor
+        // does when the guard is stored in another thread-local variable that is destroyed later,
+        // hands the value over instead of dropping it.
+        assert!(!hazard::shutdown());
+        assert_eq!(DROPPED.load(Ordering::SeqCst), 0);
+        assert!(held.as_ref().is_some());
+        drop(held);
+    })
+    .join()
+    .unwrap();
+
+    // The value is dropped by the next scan, now that the guard is gone.
+    hazard::collect();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+    assert!(cell.get().as_ref().is_none());
+}
+
+#[test]
+fn many_guards() {
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Bar(usize);
+
+    impl Drop for Bar {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    const CELLS: usize = 1000;
+
+    let cells: Vec<_> = (0..CELLS).map(|i| AtomicArc::new(Arc::new(Bar(i)))).collect();
+
+    // Growing and shrinking the entry of this thread must not lose any protected value.
+    for round in 1..4 {
+        let guards: Vec<_> = cells.iter().map(|c| c.get()).collect();
+        for (i, c) in cells.iter().enumerate() {
+            c.set(Arc::new(Bar(i)));
+        }
+
+        hazard::collect();
+        assert_eq!(DROPPED.load(Ordering::SeqCst), (round - 1) * CELLS);
+        for (i, g) in guards.iter().enumerate() {
+            assert_eq!(g.as_ref().map(|b| b.0), Some(i));
+        }
+
+        drop(guards);
+        hazard::collect();
+        assert_eq!(DROPPED.load(Ordering::SeqCst), round * CELLS);
+    }
+}
@@ -0,0 +1,125 @@
+extern crate atomic;
+extern crate crossbeam;
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::SeqCst;
+
+use atomic::{hazard, AtomicBox};
+
+static DROP_PER_THREAD: usize = 100_000;
+static N_THREADS: usize = 8;
+
+#[test]
+fn smoke() {
+    let a = AtomicBox::new(Box::new(1));
+    assert_eq!(a.get().as_ref(), Some(&1));
+
+    let s = a.get();
+    assert_eq!(a.replace(Box::new(2)).as_ref(), Some(&1));
+    assert_eq!(s.as_ref(), Some(&1));
+    assert_eq!(a.get().as_ref(), Some(&2));
+
+    assert_eq!(a.take().as_ref(), Some(&2));
+    assert_eq!(a.get().as_ref(), None);
+
+    drop(s);
+    assert_eq!(a.into_inner(), None);
+}
+
+#[test]
+fn compare_and_set() {
+    let a = AtomicBox::new(None);
+
+    let empty = a.get();
+    assert_eq!(a.compare_and_set(&empty, Box::new(1)), Ok(()));
+    assert_eq!(
+        a.compare_and_set(&empty, Box::new(2)),
+        Err(Some(Box::new(2)))
+    );
+
+    let one = a.get();
+    assert_eq!(a.compare_and_set(&one, None), Ok(()));
+    assert_eq!(one.as_ref(), Some(&1));
+
+    drop((empty, one));
+    assert_eq!(a.into_inner(), None);
+}
+
+#[test]
+fn drops() {
+    static CNT: AtomicUsize = AtomicUsize::new(0);
+
+    // Not zero-sized, so that every value gets its own address.
+    struct Foo(u8);
+
+    impl Drop for Foo {
+        fn drop(&mut self) {
+            assert_eq!(self.0, 0);
+            CNT.fetch_add(1, SeqCst);
+        }
+    }
+
+    let a = AtomicBox::new(Box::new(Foo(0)));
+
+    // Displaced values are dropped by `collect` once they are no longer protected.
+    let s = a.get();
+    a.set(Box::new(Foo(0)));
+    hazard::collect();
+    assert_eq!(CNT.load(SeqCst), 0);
+    drop(s);
+    hazard::collect();
+    assert_eq!(CNT.load(SeqCst), 1);
+
+    a.set(Box::new(Foo(0)));
+    hazard::collect();
+    assert_eq!(CNT.load(SeqCst), 2);
+
+    let s = a.take();
+    hazard::collect();
+    assert_eq!(CNT.load(SeqCst), 2);
+    drop(s);
+    hazard::collect();
+    assert_eq!(CNT.load(SeqCst), 3);
+
+    a.set(Box::new(Foo(0)));
+    drop(a);
+    hazard::collect();
+    assert_eq!(CNT.load(SeqCst), 4);
+}
+
+#[test]
+fn concurrent_replace() {
+    static CNT: AtomicUsize = AtomicUsize::new(0);
+
+    struct Foo(usize);
+
+    impl Drop for Foo {
+        fn drop(&mut self) {
+            CNT.fetch_add(1, SeqCst);
+        }
+    }
+
+    let a = AtomicBox::new(Box::new(Foo(0)));
+
+    crossbeam::scope(|s| {
+        for i in 0..N_THREADS {
+            let a = &a;
+            s.spawn(move || {
+                for j in 0..DROP_PER_THREAD {
+                    if j % 2 == 0 {
+                        a.replace(Box::new(Foo(i)));
+                    } else {
+                        let s = a.get();
+                        assert!(s.as_ref().unwrap().0 < N_THREADS);
+                    }
+                }
+            });
+        }
+    });
+
+    hazard::collect();
+    assert_eq!(CNT.load(SeqCst), N_THREADS * DROP_PER_THREAD / 2);
+    drop(a);
+    hazard::collect();
+    assert_eq!(CNT.load(SeqCst), N_THREADS * DROP_PER_THREAD / 2 + 1);
+}
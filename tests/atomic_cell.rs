@@ -1,9 +1,12 @@
 extern crate atomic;
+extern crate crossbeam;
 
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering::SeqCst;
+use std::sync::Arc;
+use std::time::Duration;
 
-use atomic::atomic_cell::AtomicCell;
+use atomic::atomic_cell::{Align16, AtomicCell};
 
 #[test]
 fn is_lock_free() {
@@ -14,9 +17,9 @@ fn is_lock_free() {
     assert_eq!(AtomicCell::<isize>::is_lock_free(), true);
     assert_eq!(AtomicCell::<UsizeWrap>::is_lock_free(), true);
 
-    assert_eq!(AtomicCell::<u8>::is_lock_free(), cfg!(feature = "nightly"));
-    assert_eq!(AtomicCell::<bool>::is_lock_free(), cfg!(feature = "nightly"));
-    assert_eq!(AtomicCell::<U8Wrap>::is_lock_free(), cfg!(feature = "nightly"));
+    assert_eq!(AtomicCell::<u8>::is_lock_free(), true);
+    assert_eq!(AtomicCell::<bool>::is_lock_free(), true);
+    assert_eq!(AtomicCell::<U8Wrap>::is_lock_free(), true);
 }
 
 #[test]
@@ -191,6 +194,20 @@ fn modular_u8() {
     assert_eq!(a.get().0, 5);
     assert_eq!(a.compare_and_set(Foo(10), Foo(15)), true);
     assert_eq!(a.get().0, 15);
+
+    assert_eq!(a.compare_exchange(Foo(20), Foo(21)).map(|x| x.0), Ok(15));
+    assert_eq!(a.compare_exchange(Foo(20), Foo(22)).map_err(|x| x.0), Err(21));
+    assert_eq!(a.compare_exchange_weak(Foo(22), Foo(23)).map_err(|x| x.0), Err(21));
+
+    let mut current = Foo(1);
+    let previous = loop {
+        match a.compare_exchange_weak(current, Foo(24)) {
+            Ok(x) => break x,
+            Err(x) => current = x,
+        }
+    };
+    assert_eq!(previous.0, 21);
+    assert_eq!(a.get().0, 24);
 }
 
 #[test]
@@ -231,4 +248,957 @@ fn modular_usize() {
     assert_eq!(a.get().0, 5);
     assert_eq!(a.compare_and_set(Foo(10), Foo(15)), true);
     assert_eq!(a.get().0, 15);
+
+    assert_eq!(a.compare_exchange(Foo(20), Foo(21)).map(|x| x.0), Ok(15));
+    assert_eq!(a.compare_exchange(Foo(20), Foo(22)).map_err(|x| x.0), Err(21));
+    assert_eq!(a.compare_exchange_weak(Foo(22), Foo(23)).map_err(|x| x.0), Err(21));
+
+    let mut current = Foo(1);
+    let previous = loop {
+        match a.compare_exchange_weak(current, Foo(24)) {
+            Ok(x) => break x,
+            Err(x) => current = x,
+        }
+    };
+    assert_eq!(previous.0, 21);
+    assert_eq!(a.get().0, 24);
+}
+
+#[test]
+fn compare_exchange() {
+    let a = AtomicCell::new(7usize);
+
+    assert_eq!(a.compare_exchange(1, 8), Err(7));
+    assert_eq!(a.compare_exchange(7, 8), Ok(7));
+    assert_eq!(a.get(), 8);
+
+    let b = AtomicCell::new([1u8; 100]);
+
+    assert_eq!(b.compare_exchange([0; 100], [2; 100]).unwrap_err()[..], [1; 100][..]);
+    assert_eq!(b.compare_exchange([1; 100], [2; 100]).unwrap()[..], [1; 100][..]);
+    assert_eq!(b.get()[..], [2; 100][..]);
+}
+
+#[test]
+fn orderings() {
+    use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
+
+    let a = AtomicCell::new(7usize);
+
+    a.store_with(8, Release);
+    assert_eq!(a.load_with(Acquire), 8);
+    assert_eq!(a.swap_with(9, AcqRel), 8);
+    assert_eq!(a.compare_exchange_with(1, 10, AcqRel, Relaxed), Err(9));
+    assert_eq!(a.compare_exchange_with(9, 10, Release, Acquire), Ok(9));
+    assert_eq!(a.add_with(5, Relaxed), 15);
+    assert_eq!(a.sub_with(3, Release), 12);
+    assert_eq!(a.load_with(Relaxed), 12);
+
+    let b = AtomicCell::new([7u8; 100]);
+
+    b.store_with([8; 100], Release);
+    assert_eq!(b.load_with(Acquire)[..], [8; 100][..]);
+    assert_eq!(b.swap_with([9; 100], AcqRel)[..], [8; 100][..]);
+    assert!(b.compare_exchange_with([1; 100], [10; 100], AcqRel, Relaxed).is_err());
+    assert!(b.compare_exchange_with([9; 100], [10; 100], SeqCst, SeqCst).is_ok());
+    assert_eq!(b.load_with(Relaxed)[..], [10; 100][..]);
+}
+
+#[test]
+#[should_panic(expected = "there is no such thing as a release load")]
+fn release_load() {
+    AtomicCell::new(7usize).load_with(std::sync::atomic::Ordering::Release);
+}
+
+#[test]
+#[should_panic(expected = "there is no such thing as a release load")]
+fn release_load_locked() {
+    AtomicCell::new([7u8; 100]).load_with(std::sync::atomic::Ordering::Release);
+}
+
+#[test]
+#[should_panic(expected = "there is no such thing as an acquire store")]
+fn acquire_store_locked() {
+    AtomicCell::new([7u8; 100]).store_with([8; 100], std::sync::atomic::Ordering::Acquire);
+}
+
+#[test]
+#[should_panic(expected = "there is no such thing as an acquire store")]
+fn acquire_store_needs_drop() {
+    AtomicCell::new(String::new()).store_with(String::new(), std::sync::atomic::Ordering::Acquire);
+}
+
+#[test]
+#[should_panic(expected = "there is no such thing as an acquire-release failure ordering")]
+fn acq_rel_failure_unit() {
+    use std::sync::atomic::Ordering::AcqRel;
+
+    AtomicCell::new(()).compare_exchange_with((), (), AcqRel, AcqRel).unwrap();
+}
+
+macro_rules! test_bitwise {
+    ($name:ident, $t:ty) => {
+        #[test]
+        fn $name() {
+            let a = AtomicCell::<$t>::new(0b1100);
+
+            assert_eq!(a.fetch_and(0b1010), 0b1100);
+            assert_eq!(a.and(0b0011), 0b0000);
+            assert_eq!(a.fetch_or(0b0101), 0b0000);
+            assert_eq!(a.or(0b1000), 0b1101);
+            assert_eq!(a.fetch_xor(0b0110), 0b1101);
+            assert_eq!(a.xor(0b0001), 0b1010);
+            assert_eq!(a.fetch_nand(0b0010), 0b1010);
+            assert_eq!(a.get(), !0b0010);
+            assert_eq!(a.nand(!0), 0b0010);
+
+            assert_eq!(a.fetch_min(5), 2);
+            assert_eq!(a.min(1), 1);
+            assert_eq!(a.fetch_max(9), 1);
+            assert_eq!(a.max(4), 9);
+            assert_eq!(a.get(), 9);
+        }
+    };
+}
+
+test_bitwise!(bitwise_u8, u8);
+test_bitwise!(bitwise_i8, i8);
+test_bitwise!(bitwise_u16, u16);
+test_bitwise!(bitwise_i16, i16);
+test_bitwise!(bitwise_u32, u32);
+test_bitwise!(bitwise_i32, i32);
+test_bitwise!(bitwise_u64, u64);
+test_bitwise!(bitwise_i64, i64);
+test_bitwise!(bitwise_usize, usize);
+test_bitwise!(bitwise_isize, isize);
+
+#[test]
+fn min_max_signed() {
+    let a = AtomicCell::new(-3i64);
+
+    assert_eq!(a.fetch_max(2), -3);
+    assert_eq!(a.fetch_min(-7), 2);
+    assert_eq!(a.max(-10), -7);
+    assert_eq!(a.min(i64::MIN), i64::MIN);
+
+    let b = AtomicCell::new(-3i8);
+
+    assert_eq!(b.fetch_max(2), -3);
+    assert_eq!(b.fetch_min(-7), 2);
+    assert_eq!(b.get(), -7);
+}
+
+#[test]
+fn concurrent_flags() {
+    const THREADS: u32 = 16;
+
+    let flags = AtomicCell::new(0u32);
+    let highest = AtomicCell::new(0u32);
+
+    crossbeam::scope(|s| {
+        for i in 0..THREADS {
+            let flags = &flags;
+            let highest = &highest;
+            s.spawn(move || {
+                assert_eq!(flags.fetch_or(1 << i) & (1 << i), 0);
+                highest.fetch_max(i);
+            });
+        }
+    });
+
+    assert_eq!(flags.get(), (1 << THREADS) - 1);
+    assert_eq!(highest.get(), THREADS - 1);
+
+    crossbeam::scope(|s| {
+        for i in 0..THREADS {
+            let flags = &flags;
+            s.spawn(move || {
+                assert_ne!(flags.fetch_and(!(1 << i)) & (1 << i), 0);
+            });
+        }
+    });
+
+    assert_eq!(flags.get(), 0);
+}
+
+#[test]
+fn logic_bool() {
+    let a = AtomicCell::new(false);
+
+    assert_eq!(a.fetch_or(true), false);
+    assert_eq!(a.fetch_and(true), true);
+    assert_eq!(a.fetch_xor(true), true);
+    assert_eq!(a.fetch_not(), false);
+    assert_eq!(a.fetch_nand(true), true);
+    assert_eq!(a.fetch_nand(false), false);
+    assert_eq!(a.fetch_and(false), true);
+    assert_eq!(a.get(), false);
+
+    // The stored byte is always a valid `bool`.
+    for _ in 0..10 {
+        a.fetch_not();
+        a.fetch_nand(true);
+        let byte = unsafe { *(a.as_ptr() as *const u8) };
+        assert!(byte == 0 || byte == 1);
+    }
+}
+
+#[test]
+fn concurrent_bool() {
+    const THREADS: usize = 8;
+    const STEPS: usize = 10_000;
+
+    let toggle = AtomicCell::new(false);
+    let lock = AtomicCell::new(false);
+    let cnt = AtomicCell::new(0usize);
+
+    crossbeam::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..STEPS {
+                    toggle.fetch_not();
+
+                    // Use the cell as a spin lock around a non-atomic increment.
+                    while lock.fetch_or(true) {}
+                    cnt.set(cnt.get() + 1);
+                    assert_eq!(lock.fetch_and(false), true);
+                }
+            });
+        }
+    });
+
+    assert_eq!(toggle.get(), false);
+    assert_eq!(cnt.get(), THREADS * STEPS);
+}
+
+#[test]
+fn fetch_update() {
+    let a = AtomicCell::new(Some(3usize));
+
+    assert_eq!(a.fetch_update(|x| x.map(|x| Some(x + 1))), Ok(Some(3)));
+    assert_eq!(a.fetch_update(|_| None), Err(Some(4)));
+    assert_eq!(a.fetch_update(|_| Some(None)), Ok(Some(4)));
+    assert_eq!(a.fetch_update(|x| x.map(|x| Some(x + 1))), Err(None));
+    assert_eq!(a.get(), None);
+
+    let b = AtomicCell::new([1u8; 100]);
+
+    assert_eq!(b.fetch_update(|x| Some([x[0] + 1; 100])).unwrap()[..], [1; 100][..]);
+    assert_eq!(b.fetch_update(|_| None).unwrap_err()[..], [2; 100][..]);
+}
+
+#[test]
+fn fetch_update_limit() {
+    const THREADS: usize = 8;
+    const STEPS: usize = 1000;
+    const LIMIT: usize = 5000;
+
+    let a = AtomicCell::new(0usize);
+    let ok = AtomicCell::new(0usize);
+
+    crossbeam::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..STEPS {
+                    if a.fetch_update(|x| if x < LIMIT { Some(x + 1) } else { None }).is_ok() {
+                        ok.add(1);
+                    }
+                }
+            });
+        }
+    });
+
+    assert_eq!(a.get(), LIMIT);
+    assert_eq!(ok.get(), LIMIT);
+}
+
+#[test]
+fn fetch_add_sub() {
+    let a = AtomicCell::new(7u8);
+
+    assert_eq!(a.fetch_add(250), 7);
+    assert_eq!(a.fetch_add(1), 1);
+    assert_eq!(a.fetch_sub(3), 2);
+    assert_eq!(a.get(), 255);
+
+    let b = AtomicCell::new(-7isize);
+
+    assert_eq!(b.fetch_add(10), -7);
+    assert_eq!(b.fetch_sub(5), 3);
+    assert_eq!(b.get(), -2);
+}
+
+#[test]
+fn checked_saturating() {
+    let a = AtomicCell::new(250u8);
+
+    assert_eq!(a.checked_add(5), Some(255));
+    assert_eq!(a.checked_add(1), None);
+    assert_eq!(a.get(), 255);
+    assert_eq!(a.saturating_add(10), 255);
+
+    a.set(3);
+    assert_eq!(a.checked_sub(4), None);
+    assert_eq!(a.checked_sub(3), Some(0));
+    assert_eq!(a.saturating_sub(10), 0);
+
+    let b = AtomicCell::new(i64::MIN + 1);
+
+    assert_eq!(b.checked_sub(2), None);
+    assert_eq!(b.saturating_sub(2), i64::MIN);
+    assert_eq!(b.checked_add(-1), None);
+    assert_eq!(b.saturating_add(i64::MAX), -1);
+}
+
+#[test]
+fn concurrent_checked_add() {
+    const THREADS: usize = 8;
+    const STEPS: usize = 100;
+
+    let a = AtomicCell::new(0u8);
+    let overflows = AtomicCell::new(0usize);
+
+    crossbeam::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..STEPS {
+                    if a.checked_add(1).is_none() {
+                        overflows.add(1);
+                    }
+                }
+            });
+        }
+    });
+
+    assert_eq!(a.get(), 255);
+    assert_eq!(overflows.get(), THREADS * STEPS - 255);
+}
+
+#[test]
+fn subword_neighbours() {
+    const STEPS: usize = 10_000;
+
+    // Narrow cells use atomics of their own size, so updates to neighbouring cells never
+    // interfere.
+    assert!(AtomicCell::<u8>::is_lock_free());
+    assert!(AtomicCell::<u16>::is_lock_free());
+    assert!(AtomicCell::<u32>::is_lock_free());
+    assert!(AtomicCell::<bool>::is_lock_free());
+
+    let bytes: Vec<AtomicCell<u8>> = (0..8).map(|_| AtomicCell::new(0)).collect();
+    let halves: Vec<AtomicCell<u16>> = (0..4).map(|_| AtomicCell::new(0)).collect();
+    let flags: Vec<AtomicCell<bool>> = (0..8).map(|_| AtomicCell::new(false)).collect();
+
+    crossbeam::scope(|s| {
+        for b in &bytes {
+            s.spawn(move || {
+                for _ in 0..STEPS {
+                    b.add(1);
+                }
+            });
+        }
+        for h in &halves {
+            s.spawn(move || {
+                for i in 0..STEPS {
+                    h.set(h.get() + 1);
+                    assert_eq!(h.replace(i as u16 + 1), i as u16 + 1);
+                }
+            });
+        }
+        for f in &flags {
+            s.spawn(move || {
+                for _ in 0..STEPS {
+                    f.fetch_not();
+                }
+            });
+        }
+    });
+
+    for b in &bytes {
+        assert_eq!(b.get(), (STEPS % 256) as u8);
+    }
+    for h in &halves {
+        assert_eq!(h.get(), STEPS as u16);
+    }
+    for f in &flags {
+        assert!(!f.get());
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C, align(16))]
+struct Pair(usize, usize);
+
+#[test]
+fn double_word_is_lock_free() {
+    #[cfg(target_arch = "x86_64")]
+    let expected = is_x86_feature_detected!("cmpxchg16b");
+    #[cfg(all(target_arch = "aarch64", target_endian = "little"))]
+    let expected = true;
+    #[cfg(not(any(
+        target_arch = "x86_64",
+        all(target_arch = "aarch64", target_endian = "little")
+    )))]
+    let expected = false;
+
+    assert_eq!(AtomicCell::<Pair>::is_lock_free(), expected);
+    assert_eq!(AtomicCell::<Align16<(usize, usize)>>::is_lock_free(), expected);
+
+    // Not aligned to 16 bytes.
+    assert_eq!(AtomicCell::<[usize; 2]>::is_lock_free(), false);
+    assert!(!AtomicCell::<(usize, usize)>::is_lock_free());
+}
+
+#[test]
+fn unaligned_double_word() {
+    const THREADS: usize = 4;
+    const STEPS: usize = 10_000;
+
+    // `(usize, usize)` goes through the locks, and the aligned version through a 16-byte
+    // compare-and-swap. Both behave the same.
+    let a = AtomicCell::new((0usize, 0usize));
+    let b = AtomicCell::new(Align16((0usize, 0usize)));
+
+    crossbeam::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..STEPS {
+                    let p = a.fetch_update(|p| Some((p.0 + 1, p.1 + 1))).unwrap();
+                    assert_eq!(p.0, p.1);
+                    let p = b.fetch_update(|p| Some(Align16((p.0 .0 + 1, p.0 .1 + 1)))).unwrap();
+                    assert_eq!(p.0 .0, p.0 .1);
+                }
+            });
+        }
+    });
+
+    assert_eq!(a.get(), (THREADS * STEPS, THREADS * STEPS));
+    assert_eq!(b.get(), Align16((THREADS * STEPS, THREADS * STEPS)));
+}
+
+#[test]
+fn double_word() {
+    let a = AtomicCell::new(Pair(1, 2));
+
+    assert_eq!(a.get(), Pair(1, 2));
+    assert_eq!(a.replace(Pair(3, 4)), Pair(1, 2));
+    assert_eq!(a.compare_exchange(Pair(3, 5), Pair(0, 0)), Err(Pair(3, 4)));
+    assert_eq!(a.compare_exchange(Pair(3, 4), Pair(5, 6)), Ok(Pair(3, 4)));
+    a.set(Pair(0, 0));
+    assert_eq!(a.get(), Pair(0, 0));
+}
+
+#[test]
+fn concurrent_double_word() {
+    const THREADS: usize = 4;
+    const STEPS: usize = 10_000;
+
+    let a = AtomicCell::new(Pair(0, 0));
+
+    crossbeam::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..STEPS {
+                    let p = a.fetch_update(|p| Some(Pair(p.0 + 1, p.1 + 1))).unwrap();
+                    assert_eq!(p.0, p.1);
+                    let p = a.get();
+                    assert_eq!(p.0, p.1);
+                }
+            });
+        }
+    });
+
+    assert_eq!(a.get(), Pair(THREADS * STEPS, THREADS * STEPS));
+}
+
+#[test]
+fn compare_exchange_bytes() {
+    use std::num::NonZeroU32;
+    use std::ptr::{self, NonNull};
+
+    use atomic::ByteEq;
+
+    fn is_byte_eq<T: ByteEq>() {}
+    is_byte_eq::<u64>();
+    is_byte_eq::<i128>();
+    is_byte_eq::<*const u8>();
+    is_byte_eq::<NonZeroU32>();
+    is_byte_eq::<Option<NonNull<String>>>();
+    is_byte_eq::<[(u8, u8); 4]>();
+
+    let a = AtomicCell::new(7u64);
+    assert_eq!(a.compare_exchange_bytes(8, 9), Err(7));
+    assert_eq!(a.compare_exchange_bytes(7, 9), Ok(7));
+    assert!(!a.compare_and_set_bytes(7, 10));
+    assert!(a.compare_and_set_bytes(9, 10));
+    assert_eq!(a.get(), 10);
+
+    let mut x = 1;
+    let p = AtomicCell::new(ptr::null_mut::<i32>());
+    assert!(p.compare_and_set_bytes(ptr::null_mut(), &mut x));
+    assert_eq!(
+        p.compare_exchange_bytes(ptr::null_mut(), ptr::null_mut()),
+        Err(&mut x as *mut _)
+    );
+
+    let n = AtomicCell::new(None::<NonNull<i32>>);
+    assert_eq!(
+        n.compare_exchange_bytes(None, NonNull::new(&mut x)),
+        Ok(None)
+    );
+    assert_eq!(n.get(), NonNull::new(&mut x));
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C, align(8))]
+struct SmallPadded {
+    a: u8,
+    b: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+struct LargePadded {
+    a: u8,
+    b: u64,
+    c: u16,
+    d: u64,
+}
+
+/// Sets the given bytes of the value in `a` to `0xff`.
+fn dirty<T>(a: &AtomicCell<T>, padding: &[usize]) {
+    let bytes = a.as_ptr() as *mut u8;
+    for &i in padding {
+        unsafe { *bytes.add(i) = 0xff };
+    }
+}
+
+// Padding bytes of the `#[repr(C)]` structs above.
+const SMALL_PADDING: &[usize] = &[1, 2, 3];
+const LARGE_PADDING: &[usize] = &[1, 2, 3, 4, 5, 6, 7, 18, 19, 20, 21, 22, 23];
+
+#[test]
+fn padded_compare_exchange() {
+    let small = SmallPadded { a: 1, b: 2 };
+    let a = AtomicCell::new(small);
+    dirty(&a, SMALL_PADDING);
+
+    assert_eq!(
+        AtomicCell::<SmallPadded>::is_lock_free(),
+        cfg!(target_pointer_width = "64")
+    );
+    assert!(a.compare_and_set(small, SmallPadded { a: 3, b: 4 }));
+    assert_eq!(a.get(), SmallPadded { a: 3, b: 4 });
+    assert_eq!(
+        a.compare_exchange(small, SmallPadded { a: 5, b: 6 }),
+        Err(SmallPadded { a: 3, b: 4 })
+    );
+
+    let large = LargePadded {
+        a: 1,
+        b: 2,
+        c: 3,
+        d: 4,
+    };
+    let b = AtomicCell::new(large);
+    dirty(&b, LARGE_PADDING);
+
+    assert!(!AtomicCell::<LargePadded>::is_lock_free());
+    assert!(b.compare_and_set(large, LargePadded { a: 5, ..large }));
+    assert_eq!(b.get().a, 5);
+    loop {
+        if b.compare_exchange_weak(LargePadded { a: 5, ..large }, large).is_ok() {
+            break;
+        }
+    }
+    assert_eq!(b.get(), large);
+}
+
+#[test]
+fn padded_update() {
+    let a = AtomicCell::new(SmallPadded { a: 0, b: 0 });
+    dirty(&a, SMALL_PADDING);
+    let b = AtomicCell::new(LargePadded {
+        a: 0,
+        b: 0,
+        c: 0,
+        d: 0,
+    });
+    dirty(&b, LARGE_PADDING);
+
+    assert_eq!(a.update(|x| SmallPadded { b: x.b + 1, ..x }).b, 1);
+    assert_eq!(b.update(|x| LargePadded { d: x.d + 1, ..x }).d, 1);
+    assert_eq!(a.fetch_update(|x| Some(SmallPadded { a: 7, ..x })).unwrap().a, 0);
+    assert_eq!(b.fetch_update(|x| Some(LargePadded { a: 7, ..x })).unwrap().a, 0);
+}
+
+#[test]
+fn concurrent_padded_compare_and_set() {
+    const THREADS: usize = 4;
+    const STEPS: u32 = 10_000;
+
+    let a = AtomicCell::new(SmallPadded { a: 0, b: 0 });
+    let b = AtomicCell::new(LargePadded {
+        a: 0,
+        b: 0,
+        c: 0,
+        d: 0,
+    });
+    dirty(&a, SMALL_PADDING);
+    dirty(&b, LARGE_PADDING);
+
+    crossbeam::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..STEPS {
+                    loop {
+                        let x = a.get();
+                        if a.compare_and_set(x, SmallPadded { b: x.b + 1, ..x }) {
+                            break;
+                        }
+                    }
+                    loop {
+                        let x = b.get();
+                        if b.compare_and_set(x, LargePadded { d: x.d + 1, ..x }) {
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    assert_eq!(a.get().b, THREADS as u32 * STEPS);
+    assert_eq!(b.get().d, THREADS as u64 * STEPS as u64);
+}
+
+#[test]
+fn concurrent_large_reads() {
+    const READERS: usize = 4;
+    const WRITES: u64 = 10_000;
+
+    let a = AtomicCell::new([0u64; 8]);
+    assert!(!AtomicCell::<[u64; 8]>::is_lock_free());
+
+    crossbeam::scope(|s| {
+        for _ in 0..READERS {
+            s.spawn(|| {
+                let mut last = 0;
+                loop {
+                    let v = a.get();
+                    // A torn read would mix elements of different writes.
+                    assert!(v.iter().all(|&x| x == v[0]));
+                    assert!(v[0] >= last);
+                    last = v[0];
+                    if last == WRITES {
+                        break;
+                    }
+                }
+            });
+        }
+
+        s.spawn(|| {
+            for i in 1..WRITES + 1 {
+                a.set([i; 8]);
+            }
+        });
+    });
+}
+
+#[test]
+fn oversubscribed_updates() {
+    // Many more threads than cores, so lock holders get preempted while others wait.
+    const THREADS: usize = 64;
+    const UPDATES: u64 = 1_000;
+
+    let a = AtomicCell::new([0u64; 4]);
+    assert!(!AtomicCell::<[u64; 4]>::is_lock_free());
+
+    crossbeam::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..UPDATES {
+                    a.update(|v| [v[0] + 1, v[1] + 1, v[2] + 1, v[3] + 1]);
+                }
+            });
+        }
+    });
+
+    assert_eq!(a.get(), [THREADS as u64 * UPDATES; 4]);
+}
+
+#[test]
+fn wait_notify() {
+    const THREADS: usize = 4;
+    const ROUNDS: u32 = 100;
+
+    // `u32` waits on a futex on Linux, `usize` and `u8` on the wait queues.
+    let a = AtomicCell::new(0u32);
+    let b = AtomicCell::new(0usize);
+    let c = AtomicCell::new(0u8);
+
+    crossbeam::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for i in 0..ROUNDS {
+                    a.wait(i);
+                    b.wait(i as usize);
+                    c.wait(i as u8);
+                }
+            });
+        }
+
+        s.spawn(|| {
+            for i in 1..ROUNDS + 1 {
+                a.set(i);
+                a.notify_all();
+                b.set(i as usize);
+                b.notify_all();
+                c.set(i as u8);
+                c.notify_all();
+            }
+        });
+    });
+}
+
+#[test]
+fn wait_timeout() {
+    let a = AtomicCell::new(7u32);
+    let b = AtomicCell::new(7u64);
+
+    assert!(!a.wait_timeout(7, Duration::from_millis(10)));
+    assert!(a.wait_timeout(8, Duration::from_millis(10)));
+    assert!(!b.wait_timeout(7, Duration::from_millis(10)));
+    assert!(b.wait_timeout(8, Duration::from_millis(10)));
+
+    crossbeam::scope(|s| {
+        s.spawn(|| {
+            a.set(8);
+            a.notify_one();
+            b.set(8);
+            b.notify_one();
+        });
+
+        assert!(a.wait_timeout(7, Duration::from_secs(60)));
+        assert!(b.wait_timeout(7, Duration::from_secs(60)));
+    });
+}
+
+#[test]
+#[should_panic(expected = "waiting is only supported on lock-free atomic cells")]
+fn wait_locked() {
+    let a = AtomicCell::new([0u8; 1000]);
+    a.wait_timeout([1; 1000], Duration::from_millis(10));
+}
+
+#[test]
+fn option_box() {
+    static CNT: AtomicUsize = AtomicUsize::new(0);
+    CNT.store(0, SeqCst);
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Foo(usize);
+
+    impl Foo {
+        fn new(val: usize) -> Foo {
+            CNT.fetch_add(1, SeqCst);
+            Foo(val)
+        }
+    }
+
+    impl Drop for Foo {
+        fn drop(&mut self) {
+            CNT.fetch_sub(1, SeqCst);
+        }
+    }
+
+    const THREADS: usize = 8;
+
+    assert!(AtomicCell::<Option<Box<Foo>>>::is_lock_free());
+    let a = AtomicCell::<Option<Box<Foo>>>::new(None);
+    let won = AtomicUsize::new(0);
+
+    crossbeam::scope(|s| {
+        for i in 0..THREADS {
+            let a = &a;
+            let won = &won;
+            s.spawn(move || {
+                if a.try_set(Box::new(Foo::new(i))).is_ok() {
+                    won.fetch_add(1, SeqCst);
+                }
+            });
+        }
+    });
+
+    assert_eq!(won.load(SeqCst), 1);
+    assert_eq!(CNT.load(SeqCst), 1);
+    assert!(a.is_some());
+
+    assert!(a.take().is_some());
+    assert!(!a.is_some());
+    assert_eq!(CNT.load(SeqCst), 0);
+}
+
+#[test]
+fn option_arc_get_or_init() {
+    const THREADS: usize = 8;
+
+    let a = AtomicCell::<Option<Arc<usize>>>::new(None);
+    assert!(AtomicCell::<Option<Arc<usize>>>::is_lock_free());
+
+    crossbeam::scope(|s| {
+        for i in 0..THREADS {
+            let a = &a;
+            s.spawn(move || {
+                let v = unsafe { a.get_or_init(|| Arc::new(i)) };
+                assert_eq!(unsafe { a.get_or_init(|| Arc::new(THREADS)) }, v);
+            });
+        }
+    });
+
+    // Only the stored `Arc` is left, the losing ones have been dropped.
+    let arc = a.take().unwrap();
+    assert!(*arc < THREADS);
+    assert_eq!(Arc::strong_count(&arc), 1);
+}
+
+#[test]
+fn option_arc_try_set() {
+    const THREADS: usize = 8;
+
+    let a = AtomicCell::<Option<Arc<usize>>>::new(None);
+    assert!(AtomicCell::<Option<Arc<usize>>>::is_lock_free());
+    let stored = AtomicUsize::new(0);
+
+    crossbeam::scope(|s| {
+        for i in 0..THREADS {
+            let a = &a;
+            let stored = &stored;
+            s.spawn(move || match a.try_set(Arc::new(i)) {
+                Ok(()) => {
+                    stored.fetch_add(1, SeqCst);
+                }
+                Err(arc) => assert_eq!(*arc, i),
+            });
+        }
+    });
+
+    // Only one `Arc` has been stored, the others have been given back and dropped.
+    assert_eq!(stored.load(SeqCst), 1);
+    let arc = a.take().unwrap();
+    assert!(*arc < THREADS);
+    assert_eq!(Arc::strong_count(&arc), 1);
+}
+
+/// Shares a pointer cell between threads, which `AtomicCell<*mut T>` doesn't allow by itself.
+struct SharedPtrCell<T>(AtomicCell<*mut T>);
+
+unsafe impl<T> Sync for SharedPtrCell<T> {}
+
+#[test]
+fn concurrent_ptr_add() {
+    const THREADS: usize = 4;
+    const STEPS: usize = 1_000;
+
+    // A bump allocator handing out elements of `buf`.
+    let mut buf = vec![0u64; THREADS * STEPS];
+    let start = buf.as_mut_ptr();
+    let a = SharedPtrCell(AtomicCell::new(start));
+
+    crossbeam::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..STEPS {
+                    unsafe { *a.0.fetch_ptr_add(1) += 1 };
+                }
+            });
+        }
+    });
+
+    let a = a.0;
+    assert_eq!(a.get(), start.wrapping_add(THREADS * STEPS));
+    assert!(buf.iter().all(|&x| x == 1));
+
+    a.fetch_ptr_sub(THREADS * STEPS);
+    assert_eq!(a.get(), start);
+}
+
+#[test]
+fn concurrent_tag() {
+    const THREADS: usize = 8;
+
+    let mut x = 0u64;
+    let p = &mut x as *mut u64;
+    let addr = p as usize;
+    let a = SharedPtrCell(AtomicCell::new(p));
+    let marked = AtomicUsize::new(0);
+
+    // Exactly one thread gets to mark the pointer.
+    crossbeam::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                if a.0.fetch_or_tag(1) as usize == addr {
+                    marked.fetch_add(1, SeqCst);
+                }
+            });
+        }
+    });
+    assert_eq!(marked.load(SeqCst), 1);
+
+    let a = a.0;
+    // Arithmetic and tags don't interfere, and tags never spill into the address.
+    a.fetch_ptr_add(3);
+    assert_eq!(a.get() as usize, p.wrapping_add(3) as usize | 1);
+    a.with_tag(!0);
+    assert_eq!(a.get() as usize, p.wrapping_add(3) as usize | 7);
+    a.fetch_and_tag(2);
+    assert_eq!(a.get() as usize, p.wrapping_add(3) as usize | 2);
+    a.fetch_ptr_sub(3);
+    a.fetch_and_tag(0);
+    assert_eq!(a.get(), p);
+}
+
+#[test]
+fn lock_stripes() {
+    let expected = match option_env!("ATOMIC_CELL_LOCK_STRIPES") {
+        Some(s) => s.trim().parse().unwrap(),
+        None => 499,
+    };
+    assert_eq!(atomic::atomic_cell::LOCK_STRIPES, expected);
+}
+
+/// Returns two distinct cells of `cells` that share a lock.
+fn same_stripe<T>(cells: &[AtomicCell<T>]) -> (&AtomicCell<T>, &AtomicCell<T>) {
+    use atomic::atomic_cell::LOCK_STRIPES;
+    use std::collections::HashMap;
+
+    // There are more cells than locks, so two of them must share one.
+    assert!(cells.len() > LOCK_STRIPES);
+
+    let mut seen = HashMap::new();
+    for c in cells {
+        if let Some(other) = seen.insert(c.as_ptr() as usize % LOCK_STRIPES, c) {
+            return (other, c);
+        }
+    }
+    unreachable!()
+}
+
+#[test]
+fn nested_update_same_stripe() {
+    use atomic::atomic_cell::LOCK_STRIPES;
+
+    let cells: Vec<_> = (0..=LOCK_STRIPES)
+        .map(|_| AtomicCell::new([0usize; 3]))
+        .collect();
+    let (a, b) = same_stripe(&cells);
+    assert!(!AtomicCell::<[usize; 3]>::is_lock_free());
+
+    // The closure runs without holding the lock, so it can use a cell sharing it.
+    a.update(|x| {
+        b.update(|y| [y[0] + 1, y[1], y[2]]);
+        [x[0] + 1, x[1], x[2]]
+    });
+    assert_eq!(a.get(), [1, 0, 0]);
+    assert_eq!(b.get(), [1, 0, 0]);
 }
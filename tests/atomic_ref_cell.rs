@@ -1,6 +1,117 @@
 extern crate atomic;
+extern crate crossbeam;
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::SeqCst;
+
+use atomic::atomic_ref_cell::AtomicRefCell;
+
+#[test]
+fn shared_borrows() {
+    let c = AtomicRefCell::new(5);
+
+    let a = c.borrow();
+    let b = c.try_borrow().unwrap();
+    assert_eq!(*a, 5);
+    assert_eq!(*b, 5);
+    assert!(c.try_borrow_mut().is_err());
+
+    drop(a);
+    assert!(c.try_borrow_mut().is_err());
+
+    drop(b);
+    assert!(c.try_borrow_mut().is_ok());
+}
 
 #[test]
-fn smoke() {
-    // TODO
+fn mutable_borrow() {
+    let c = AtomicRefCell::new(vec![1, 2]);
+
+    {
+        let mut m = c.borrow_mut();
+        m.push(3);
+        assert!(c.try_borrow().is_err());
+        assert!(c.try_borrow_mut().is_err());
+    }
+
+    assert_eq!(*c.borrow(), [1, 2, 3]);
+    assert_eq!(c.into_inner(), [1, 2, 3]);
+}
+
+#[test]
+fn errors() {
+    let c = AtomicRefCell::new(());
+
+    let m = c.borrow_mut();
+    assert_eq!(c.try_borrow().unwrap_err().to_string(), "already mutably borrowed");
+    drop(m);
+
+    let b = c.borrow();
+    assert_eq!(c.try_borrow_mut().unwrap_err().to_string(), "already borrowed");
+    drop(b);
+}
+
+#[test]
+fn debug() {
+    let c = AtomicRefCell::new(7);
+    assert_eq!(format!("{:?}", c), "AtomicRefCell { value: 7 }");
+
+    let m = c.borrow_mut();
+    assert_eq!(format!("{:?}", c), "AtomicRefCell { value: \"<borrowed>\" }");
+    drop(m);
+}
+
+#[test]
+fn unsized_value() {
+    let c: &AtomicRefCell<[i32]> = &AtomicRefCell::new([1, 2, 3]);
+
+    c.borrow_mut()[1] = 5;
+    assert_eq!(&*c.borrow(), &[1, 5, 3][..]);
+}
+
+#[test]
+fn drops() {
+    static CNT: AtomicUsize = AtomicUsize::new(0);
+
+    struct Foo;
+
+    impl Drop for Foo {
+        fn drop(&mut self) {
+            CNT.fetch_add(1, SeqCst);
+        }
+    }
+
+    let c = AtomicRefCell::new(Foo);
+    *c.borrow_mut() = Foo;
+    assert_eq!(CNT.load(SeqCst), 1);
+
+    drop(c);
+    assert_eq!(CNT.load(SeqCst), 2);
+}
+
+#[test]
+fn concurrent() {
+    const THREADS: usize = 8;
+    const STEPS: usize = 10_000;
+
+    let c = AtomicRefCell::new((0usize, 0usize));
+
+    crossbeam::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..STEPS {
+                    {
+                        let mut m = c.borrow_mut();
+                        m.0 += 1;
+                        m.1 += 1;
+                    }
+
+                    let b = c.borrow();
+                    assert_eq!(b.0, b.1);
+                }
+            });
+        }
+    });
+
+    assert_eq!(c.into_inner(), (THREADS * STEPS, THREADS * STEPS));
 }
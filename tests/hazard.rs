@@ -0,0 +1,175 @@
+extern crate atomic;
+extern crate crossbeam;
+
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use atomic::hazard::{self, HazardPointer};
+use atomic::{AtomicArc, AtomicBox};
+
+/// `shutdown` checks the whole registry, so tests using it can't run concurrently.
+fn serialize() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[test]
+fn shutdown() {
+    let _lock = serialize();
+
+    const THREADS: usize = 16;
+    const GUARDS: usize = 50;
+
+    let a = AtomicArc::new(Arc::new(7));
+    let b = AtomicBox::new(Box::new(8));
+
+    // Holding many guards at once grows the entries.
+    crossbeam::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                let guards: Vec<_> = (0..GUARDS).map(|_| a.get()).collect();
+                assert!(guards.iter().all(|g| g.as_ref() == Some(&7)));
+                assert_eq!(b.get().as_ref(), Some(&8));
+            });
+        }
+    });
+
+    // An entry can't be freed while one of its slots is in use.
+    let guard = a.get();
+    assert!(!hazard::shutdown());
+    drop(guard);
+    assert!(hazard::shutdown());
+
+    // The registry is usable again afterwards.
+    a.set(Arc::new(9));
+    assert_eq!(a.get().as_ref(), Some(&9));
+    assert!(hazard::shutdown());
+}
+
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+struct Node {
+    value: usize,
+    next: *mut Node,
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        DROPPED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+unsafe fn drop_node(ptr: *mut Node) {
+    drop(Box::from_raw(ptr));
+}
+
+#[test]
+fn protect_reset() {
+    let _lock = serialize();
+    DROPPED.store(0, Ordering::SeqCst);
+
+    let new_node = |value| Box::into_raw(Box::new(Node { value, next: ptr::null_mut() }));
+    let a = AtomicPtr::new(new_node(1));
+
+    let mut hp = HazardPointer::new();
+    assert_eq!(unsafe { hp.protect(&a).as_ref() }.map(|n| n.value), Some(1));
+
+    // The value stays protected after the guard is gone, until the hazard pointer is reset.
+    unsafe { hazard::retire(a.swap(new_node(2), Ordering::AcqRel), drop_node) };
+    hazard::collect();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 0);
+    hp.reset();
+    hazard::collect();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+
+    // Protecting another value releases the previous one.
+    assert_eq!(unsafe { hp.protect(&a).as_ref() }.map(|n| n.value), Some(2));
+    unsafe { hazard::retire(a.swap(ptr::null_mut(), Ordering::AcqRel), drop_node) };
+    assert!(hp.protect(&a).as_ptr().is_null());
+    hazard::collect();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 2);
+
+    // A hazard pointer keeps its slot even when it protects nothing.
+    assert!(!hazard::shutdown());
+    drop(hp);
+    assert!(hazard::shutdown());
+}
+
+#[test]
+fn treiber_stack() {
+    const THREADS: usize = 8;
+    const COUNT: usize = 10_000;
+
+    struct Stack {
+        head: AtomicPtr<Node>,
+    }
+
+    impl Stack {
+        fn push(&self, value: usize) {
+            let node = Box::into_raw(Box::new(Node { value, next: ptr::null_mut() }));
+            loop {
+                let head = self.head.load(Ordering::Relaxed);
+                unsafe { (*node).next = head };
+                if self
+                    .head
+                    .compare_exchange(head, node, Ordering::Release, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    return;
+                }
+            }
+        }
+
+        fn pop(&self, hp: &mut HazardPointer) -> Option<usize> {
+            loop {
+                let head = hp.protect(&self.head).as_ptr();
+                if head.is_null() {
+                    return None;
+                }
+
+                // Without the hazard pointer, `head` could have been dropped by another thread.
+                let next = unsafe { (*head).next };
+                if self
+                    .head
+                    .compare_exchange(head, next, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    let value = unsafe { (*head).value };
+                    hp.reset();
+                    unsafe { hazard::retire(head, drop_node) };
+                    return Some(value);
+                }
+            }
+        }
+    }
+
+    let _lock = serialize();
+    DROPPED.store(0, Ordering::SeqCst);
+
+    let stack = Stack {
+        head: AtomicPtr::new(ptr::null_mut()),
+    };
+    let sum = AtomicUsize::new(0);
+
+    crossbeam::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                let mut hp = HazardPointer::new();
+                for i in 0..COUNT {
+                    stack.push(i);
+                    let value = stack.pop(&mut hp).unwrap();
+                    sum.fetch_add(value, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+
+    assert!(stack.head.load(Ordering::Relaxed).is_null());
+    assert_eq!(sum.load(Ordering::Relaxed), THREADS * COUNT * (COUNT - 1) / 2);
+
+    // Values left over by the threads are handed over when they exit.
+    hazard::collect();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), THREADS * COUNT);
+    assert!(hazard::shutdown());
+}